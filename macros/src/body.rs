@@ -0,0 +1,52 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{Block, Path, ReturnType, Signature, Type};
+
+/// An expression running `block`, the body of the function `sig`, to its
+/// output, so that `return` and `?` in the body do not skip the code after it.
+pub(crate) fn run(sig: &Signature, block: &Block) -> TokenStream {
+    // Without an explicit type, `?` in the body could not infer its error type.
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) if !is_impl_trait(ty) => quote!(let __drcode_output: #ty = #block;),
+        _ => quote!(let __drcode_output = #block;),
+    };
+    if sig.asyncness.is_some() {
+        quote!(async move { #output __drcode_output })
+    } else {
+        quote!((move || { #output __drcode_output })())
+    }
+}
+
+/// An expression reporting the error in `__drcode_output` and evaluating to
+/// whether there was one, or `None` if the function does not return `Result`.
+pub(crate) fn report(sig: &Signature, krate: &Path) -> Option<TokenStream> {
+    returns_result(&sig.output).then(|| {
+        quote! {
+            {
+                #[allow(unused_imports)]
+                use #krate::__private::{ReportDebug, ReportDynError, ReportStdError};
+                (&&&#krate::__private::Output(&__drcode_output)).report_output()
+            }
+        }
+    })
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+fn is_impl_trait(ty: &Type) -> bool {
+    ty.to_token_stream().into_iter().any(|token| {
+        matches!(token, proc_macro2::TokenTree::Ident(ident) if ident == "impl")
+    })
+}
@@ -0,0 +1,93 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::{ItemFn, LitStr, Path};
+
+struct Args {
+    tokio: bool,
+    krate: Path,
+}
+
+impl Args {
+    fn parse(args: TokenStream) -> syn::Result<Args> {
+        let mut parsed = Args {
+            tokio: false,
+            krate: syn::parse_quote!(::drcode_rust),
+        };
+
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("tokio") {
+                parsed.tokio = true;
+            } else if meta.path.is_ident("crate") {
+                parsed.krate = meta.value()?.parse::<LitStr>()?.parse()?;
+            } else {
+                return Err(meta.error("expected `tokio` or `crate`"));
+            }
+            Ok(())
+        });
+        parser.parse2(args)?;
+        Ok(parsed)
+    }
+}
+
+pub(crate) fn expand(args: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let args = Args::parse(args)?;
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = syn::parse2(item)?;
+
+    match (sig.asyncness.is_some(), args.tokio) {
+        (true, false) => {
+            return Err(syn::Error::new_spanned(
+                sig.asyncness,
+                "an async main function needs `#[drcode_rust::main(tokio)]`",
+            ))
+        }
+        (false, true) => {
+            return Err(syn::Error::new_spanned(
+                sig.fn_token,
+                "`tokio` needs an async main function",
+            ))
+        }
+        _ => {}
+    }
+
+    let krate = &args.krate;
+    let run = crate::body::run(&sig, &block);
+    let run = if args.tokio {
+        quote! {
+            #krate::__private::tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("failed to build the Tokio runtime")
+                .block_on(#run)
+        }
+    } else {
+        run
+    };
+    let report = crate::body::report(&sig, krate).map(|report| {
+        quote! {
+            #krate::with_scope(
+                |scope| scope.set_level(::std::option::Option::Some(#krate::Level::Fatal)),
+                || #report,
+            );
+        }
+    });
+    sig.asyncness = None;
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            let __drcode_guard = #krate::__private::init_main();
+            #[allow(unreachable_code, clippy::redundant_closure_call)]
+            let __drcode_output = #run;
+            #report
+            // Flush pending events before returning, which may exit the process.
+            ::std::mem::drop(__drcode_guard);
+            __drcode_output
+        }
+    })
+}
@@ -0,0 +1,115 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::{FnArg, Ident, ItemFn, LitStr, Pat, Path};
+
+struct Args {
+    op: String,
+    name: Option<String>,
+    skip: Vec<Ident>,
+    krate: Path,
+}
+
+impl Args {
+    fn parse(args: TokenStream) -> syn::Result<Args> {
+        let mut parsed = Args {
+            op: "function".to_string(),
+            name: None,
+            skip: Vec::new(),
+            krate: syn::parse_quote!(::drcode_rust),
+        };
+
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("op") {
+                parsed.op = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("name") {
+                parsed.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("skip") {
+                meta.parse_nested_meta(|meta| {
+                    parsed.skip.push(meta.path.require_ident()?.clone());
+                    Ok(())
+                })?;
+            } else if meta.path.is_ident("crate") {
+                parsed.krate = meta.value()?.parse::<LitStr>()?.parse()?;
+            } else {
+                return Err(meta.error("expected `op`, `name`, `skip` or `crate`"));
+            }
+            Ok(())
+        });
+        parser.parse2(args)?;
+        Ok(parsed)
+    }
+}
+
+pub(crate) fn expand(args: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let args = Args::parse(args)?;
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = syn::parse2(item)?;
+
+    for skipped in &args.skip {
+        if !recorded_args(&sig.inputs).any(|arg| arg == skipped) {
+            return Err(syn::Error::new_spanned(
+                skipped,
+                "`skip` names an argument the function does not have",
+            ));
+        }
+    }
+
+    let krate = &args.krate;
+    let op = &args.op;
+    let name = args.name.unwrap_or_else(|| sig.ident.to_string());
+    let record = recorded_args(&sig.inputs)
+        .filter(|arg| !args.skip.contains(arg))
+        .map(|arg| {
+            let key = arg.to_string();
+            quote!(__drcode_span.set_data(#key, ::std::format!("{:?}", &#arg));)
+        });
+
+    // The span of an async function is only current while its future is
+    // polled, so that futures polled concurrently do not become its children.
+    let run = crate::body::run(&sig, &block);
+    let (start, run) = match sig.asyncness {
+        Some(_) => (
+            quote!(#krate::__private::start_async_span(#op, #name)),
+            quote!(#krate::__private::instrument(&__drcode_span, #run).await),
+        ),
+        None => (quote!(#krate::start_span(#op, #name)), run),
+    };
+    let report = crate::body::report(&sig, krate).map(|report| {
+        quote! {
+            if #report {
+                __drcode_span.set_status(#krate::SpanStatus::InternalError);
+            }
+        }
+    });
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            let __drcode_span = #start;
+            #(#record)*
+            #[allow(unreachable_code, clippy::redundant_closure_call)]
+            let __drcode_output = #run;
+            #report
+            __drcode_output
+        }
+    })
+}
+
+/// The names of the arguments that can be recorded, leaving out `self` and
+/// destructured arguments.
+fn recorded_args(
+    inputs: &syn::punctuated::Punctuated<FnArg, syn::Token![,]>,
+) -> impl Iterator<Item = &Ident> {
+    inputs.iter().filter_map(|arg| match arg {
+        FnArg::Typed(arg) => match &*arg.pat {
+            Pat::Ident(pat) => Some(&pat.ident),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    })
+}
@@ -0,0 +1,27 @@
+//! Attribute macros for `drcode-rust`. Use them through the re-exports in `drcode_rust`.
+
+use proc_macro::TokenStream;
+
+mod body;
+mod instrument;
+mod entry;
+
+/// Wrap a function in a DrCode span.
+///
+/// See the documentation of `drcode_rust::instrument`.
+#[proc_macro_attribute]
+pub fn instrument(args: TokenStream, item: TokenStream) -> TokenStream {
+    instrument::expand(args.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Initialize DrCode around a `main` function.
+///
+/// See the documentation of `drcode_rust::main`.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    entry::expand(args.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
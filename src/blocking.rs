@@ -0,0 +1,146 @@
+use crate::{panic, report_error, TaskError};
+use sentry::protocol::{Context, Map, Value};
+use sentry::Hub;
+use std::sync::Arc;
+use std::thread;
+
+/// Run a closure with automatic error reporting.
+///
+/// The error returned by `f` is reported, and so is a panic in `f`, which is
+/// caught and returned as [`TaskError::Panic`]. Use it for blocking code and
+/// the body of threads.
+///
+/// ```no_run
+/// use drcode_rust::run_sync_with_error_reporting;
+///
+/// let result = run_sync_with_error_reporting(|| std::fs::read_to_string("orders.csv"));
+/// ```
+pub fn run_sync_with_error_reporting<F, T, E>(f: F) -> Result<T, TaskError<E>>
+where
+    F: FnOnce() -> Result<T, E>,
+    E: std::error::Error,
+{
+    match panic::catch_unwind(f) {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => {
+            report_error(&e);
+            Err(TaskError::Error(e))
+        }
+        Err(payload) => Err(crate::task_panic(payload)),
+    }
+}
+
+/// Spawn a named thread that runs `f` with [`run_sync_with_error_reporting`].
+///
+/// The thread gets its own hub with a copy of the caller's scope, and its
+/// name is added to reported events as the `thread` context.
+pub fn spawn_thread_reported<F, T, E>(
+    name: impl Into<String>,
+    f: F,
+) -> std::io::Result<thread::JoinHandle<Result<T, TaskError<E>>>>
+where
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: std::error::Error + Send + 'static,
+{
+    let hub = fork_hub();
+    thread::Builder::new()
+        .name(name.into())
+        .spawn(move || run_reported(hub, f))
+}
+
+/// Run `f` on the Tokio blocking thread pool with [`run_sync_with_error_reporting`].
+///
+/// Like [`spawn_thread_reported`], the closure gets its own hub with a copy
+/// of the caller's scope and the thread it runs on is added to reported
+/// events as the `thread` context.
+///
+/// # Panics
+///
+/// Panics if called outside of a Tokio runtime.
+pub fn spawn_blocking_reported<F, T, E>(f: F) -> tokio::task::JoinHandle<Result<T, TaskError<E>>>
+where
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: std::error::Error + Send + 'static,
+{
+    let hub = fork_hub();
+    tokio::task::spawn_blocking(move || run_reported(hub, f))
+}
+
+fn fork_hub() -> Arc<Hub> {
+    Arc::new(Hub::new_from_top(Hub::current()))
+}
+
+fn run_reported<F, T, E>(hub: Arc<Hub>, f: F) -> Result<T, TaskError<E>>
+where
+    F: FnOnce() -> Result<T, E>,
+    E: std::error::Error,
+{
+    Hub::run(hub, || {
+        let current = thread::current();
+        let mut context = Map::new();
+        context.insert(
+            "name".to_string(),
+            Value::from(current.name().unwrap_or("<unnamed>")),
+        );
+        context.insert("id".to_string(), format!("{:?}", current.id()).into());
+        sentry::configure_scope(|scope| scope.set_context("thread", Context::Other(context)));
+
+        run_sync_with_error_reporting(f)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn thread_name(event: &sentry::protocol::Event<'_>) -> Option<Value> {
+        match event.contexts.get("thread") {
+            Some(Context::Other(context)) => context.get("name").cloned(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_run_sync_with_error_reporting() {
+        let events = sentry::test::with_captured_events(|| {
+            assert_eq!(run_sync_with_error_reporting(|| Ok::<_, io::Error>(1)).unwrap(), 1);
+            let error = run_sync_with_error_reporting(|| Err::<(), _>(io::Error::other("failed")));
+            assert!(matches!(error, Err(TaskError::Error(_))));
+            let panic = run_sync_with_error_reporting(|| -> io::Result<()> { panic!("sync panic") });
+            assert!(matches!(panic, Err(TaskError::Panic { .. })));
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].exception[0].value.as_deref(), Some("failed"));
+        assert_eq!(events[1].exception[0].value.as_deref(), Some("sync panic"));
+    }
+
+    #[test]
+    fn test_spawn_reported() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            sentry::configure_scope(|scope| scope.set_tag("job", "import"));
+
+            let thread = spawn_thread_reported("importer", || {
+                Err::<(), _>(io::Error::other("thread failed"))
+            })
+            .unwrap();
+            assert!(matches!(thread.join().unwrap(), Err(TaskError::Error(_))));
+
+            let result = runtime.block_on(async {
+                spawn_blocking_reported(|| Err::<(), _>(io::Error::other("blocking failed")))
+                    .await
+                    .unwrap()
+            });
+            assert!(matches!(result, Err(TaskError::Error(_))));
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(thread_name(&events[0]), Some(Value::from("importer")));
+        assert!(thread_name(&events[1]).is_some());
+        assert!(events.iter().all(|event| event.tags["job"] == "import"));
+    }
+}
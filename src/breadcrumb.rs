@@ -0,0 +1,104 @@
+use crate::Level;
+use sentry::protocol::{Map, Value};
+
+pub use sentry::Breadcrumb;
+
+/// Record a breadcrumb on the current scope.
+///
+/// Breadcrumbs are attached to the next events sent from this scope, whether
+/// by [`report_error`](crate::report_error), the panic hook or any other
+/// capture. Only the most recent `Config::max_breadcrumbs` are kept.
+///
+/// ```no_run
+/// use drcode_rust::{add_breadcrumb, Breadcrumb, Level};
+///
+/// add_breadcrumb(Breadcrumb {
+///     category: Some("auth".to_string()),
+///     message: Some("user signed in".to_string()),
+///     level: Level::Info,
+///     ..Default::default()
+/// });
+/// ```
+pub fn add_breadcrumb(breadcrumb: Breadcrumb) {
+    sentry::add_breadcrumb(breadcrumb);
+}
+
+/// Record an outgoing or incoming HTTP request.
+///
+/// Responses with a 4xx status are recorded at warning level and responses
+/// with a 5xx status at error level.
+pub fn add_http_breadcrumb(method: &str, url: &str, status_code: Option<u16>) {
+    let mut data = Map::new();
+    data.insert("method".to_string(), method.into());
+    data.insert("url".to_string(), url.into());
+    if let Some(status_code) = status_code {
+        data.insert("status_code".to_string(), status_code.into());
+    }
+
+    add_breadcrumb(Breadcrumb {
+        ty: "http".to_string(),
+        category: Some("http".to_string()),
+        level: match status_code {
+            Some(500..) => Level::Error,
+            Some(400..) => Level::Warning,
+            _ => Level::Info,
+        },
+        data,
+        ..Default::default()
+    });
+}
+
+/// Record a database query.
+pub fn add_query_breadcrumb(query: &str) {
+    add_breadcrumb(Breadcrumb {
+        ty: "query".to_string(),
+        category: Some("query".to_string()),
+        message: Some(query.to_string()),
+        ..Default::default()
+    });
+}
+
+/// Record a navigation from one location, such as a route or page, to another.
+pub fn add_navigation_breadcrumb(from: &str, to: &str) {
+    let mut data = Map::new();
+    data.insert("from".to_string(), Value::from(from));
+    data.insert("to".to_string(), Value::from(to));
+
+    add_breadcrumb(Breadcrumb {
+        ty: "navigation".to_string(),
+        category: Some("navigation".to_string()),
+        data,
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_error;
+
+    #[test]
+    fn test_breadcrumbs() {
+        let events = sentry::test::with_captured_events(|| {
+            add_breadcrumb(Breadcrumb {
+                category: Some("auth".to_string()),
+                message: Some("user signed in".to_string()),
+                ..Default::default()
+            });
+            add_http_breadcrumb("GET", "https://example.com/orders", Some(503));
+            add_query_breadcrumb("SELECT * FROM orders");
+            add_navigation_breadcrumb("/cart", "/checkout");
+            report_error(&std::io::Error::other("checkout failed"));
+        });
+
+        assert_eq!(events.len(), 1);
+        let breadcrumbs = &events[0].breadcrumbs.values;
+        assert_eq!(breadcrumbs.len(), 4);
+        assert_eq!(breadcrumbs[0].message.as_deref(), Some("user signed in"));
+        assert_eq!(breadcrumbs[1].ty, "http");
+        assert_eq!(breadcrumbs[1].level, Level::Error);
+        assert_eq!(breadcrumbs[1].data["status_code"], Value::from(503));
+        assert_eq!(breadcrumbs[2].message.as_deref(), Some("SELECT * FROM orders"));
+        assert_eq!(breadcrumbs[3].data["to"], Value::from("/checkout"));
+    }
+}
@@ -0,0 +1,157 @@
+use sentry::protocol::{Event, Exception, Level, Map, Mechanism};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default maximum number of errors reported from one source chain.
+pub const DEFAULT_MAX_ERROR_CHAIN_DEPTH: usize = 10;
+
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ERROR_CHAIN_DEPTH);
+
+pub(crate) fn set_max_depth(depth: usize) {
+    MAX_DEPTH.store(depth.max(1), Ordering::Relaxed);
+}
+
+/// Build an event with one exception per error in the `source()` chain of `err`.
+///
+/// Exceptions are ordered from the root cause to `err` itself, which is the
+/// order DrCode displays chained exceptions in. Only the outermost errors up
+/// to the configured maximum depth are included.
+pub(crate) fn event_from_error<E: Error + ?Sized>(err: &E) -> Event<'static> {
+    let max_depth = MAX_DEPTH.load(Ordering::Relaxed);
+
+    let mut exceptions = vec![exception_from_error(err)];
+    let mut source = err.source();
+    while let Some(err) = source {
+        if exceptions.len() == max_depth {
+            break;
+        }
+        exceptions.push(exception_from_error(err));
+        source = err.source();
+    }
+
+    event_from_exceptions(exceptions)
+}
+
+/// Build an event from exceptions ordered from the outermost error to the root cause.
+pub(crate) fn event_from_exceptions(mut exceptions: Vec<Exception>) -> Event<'static> {
+    let len = exceptions.len();
+    for (depth, exception) in exceptions.iter_mut().enumerate().skip(1) {
+        let mut data = Map::new();
+        data.insert("depth".to_string(), depth.into());
+        exception.mechanism = Some(Mechanism {
+            ty: "chained".to_string(),
+            description: Some(format!("cause {} of {}", depth, len - 1)),
+            data,
+            ..Default::default()
+        });
+    }
+
+    exceptions.reverse();
+    Event {
+        exception: exceptions.into(),
+        level: Level::Error,
+        ..Default::default()
+    }
+}
+
+pub(crate) fn exception_from_error<E: Error + ?Sized>(err: &E) -> Exception {
+    let value = err.to_string();
+    let debug = format!("{:?}", err);
+
+    // Errors whose `Debug` output is just their quoted message carry no type name.
+    let ty = if debug == format!("{:?}", value) {
+        "Error".to_string()
+    } else {
+        sentry::parse_type_from_debug(&debug).to_string()
+    };
+
+    Exception {
+        ty,
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct LayerError {
+        layer: usize,
+        source: Option<Box<LayerError>>,
+        io: Option<std::io::Error>,
+    }
+
+    impl LayerError {
+        fn new(layers: usize) -> LayerError {
+            let mut error = LayerError {
+                layer: 0,
+                source: None,
+                io: Some(std::io::Error::other("disk full")),
+            };
+            for layer in 1..layers {
+                error = LayerError {
+                    layer,
+                    source: Some(Box::new(error)),
+                    io: None,
+                };
+            }
+            error
+        }
+    }
+
+    impl fmt::Display for LayerError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "layer {} failed", self.layer)
+        }
+    }
+
+    impl Error for LayerError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match (&self.source, &self.io) {
+                (Some(source), _) => Some(source.as_ref()),
+                (None, Some(io)) => Some(io),
+                (None, None) => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let event = event_from_error(&LayerError::new(3));
+        let exceptions = &event.exception.values;
+
+        assert_eq!(exceptions.len(), 4);
+        assert_eq!(exceptions[0].ty, "Custom");
+        assert_eq!(exceptions[0].value.as_deref(), Some("disk full"));
+        assert_eq!(exceptions[3].ty, "LayerError");
+        assert_eq!(exceptions[3].value.as_deref(), Some("layer 2 failed"));
+        assert!(exceptions[3].mechanism.is_none());
+        assert_eq!(
+            exceptions[0].mechanism.as_ref().map(|m| m.ty.as_str()),
+            Some("chained")
+        );
+    }
+
+    #[test]
+    fn test_error_chain_depth() {
+        let exceptions = (0..20)
+            .map(|i| Exception {
+                ty: "Error".to_string(),
+                value: Some(i.to_string()),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(event_from_exceptions(exceptions).exception.len(), 20);
+
+        let event = event_from_error(&LayerError::new(DEFAULT_MAX_ERROR_CHAIN_DEPTH + 5));
+        let exceptions = &event.exception.values;
+        assert_eq!(exceptions.len(), DEFAULT_MAX_ERROR_CHAIN_DEPTH);
+        assert_eq!(
+            exceptions.last().unwrap().value.as_deref(),
+            Some("layer 14 failed")
+        );
+    }
+}
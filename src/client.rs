@@ -0,0 +1,94 @@
+use crate::{Config, DrCodeError, Level, PanicHook, PanicHookGuard, Uuid};
+use sentry::ClientInitGuard;
+use std::time::Duration;
+
+/// A DrCode client bound to the process-wide hub.
+///
+/// The client flushes all pending events when dropped, so keep it alive for
+/// as long as events should be reported, usually for the whole of `main`.
+pub struct DrCode {
+    _panic_hook: Option<PanicHookGuard>,
+    guard: ClientInitGuard,
+}
+
+impl DrCode {
+    /// Validate the configuration and initialize a new client.
+    ///
+    /// Nothing is initialized when the configuration is invalid.
+    pub fn new(config: Config) -> Result<DrCode, DrCodeError> {
+        let guard = crate::init_client(&config)?;
+        if !guard.is_enabled() {
+            return Err(DrCodeError::InitializationError(
+                "the client is disabled for this configuration".to_string(),
+            ));
+        }
+        Ok(DrCode {
+            _panic_hook: None,
+            guard,
+        })
+    }
+
+    /// Install the DrCode panic hook with the given options for as long as
+    /// the client is alive.
+    ///
+    /// Dropping the client restores the hook that was installed before.
+    pub fn with_panic_hook(mut self, hook: PanicHook) -> Self {
+        self._panic_hook = Some(hook.install());
+        self
+    }
+
+    /// Whether events are being sent.
+    pub fn is_enabled(&self) -> bool {
+        self.guard.is_enabled()
+    }
+
+    /// Capture a message with the given severity level.
+    pub fn capture_message(&self, message: &str, level: Level) -> Uuid {
+        crate::capture_message(message, level)
+    }
+
+    /// Capture an error along with its `source()` chain.
+    pub fn capture_error<E: std::error::Error + ?Sized>(&self, error: &E) -> Uuid {
+        crate::report_error(error)
+    }
+
+    /// Wait up to `timeout` for pending events to be sent.
+    ///
+    /// Returns `true` if the queue was drained in time.
+    pub fn flush(&self, timeout: Option<Duration>) -> bool {
+        self.guard.flush(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let drcode = DrCode::new(Config::new("test_key", "test_project")).unwrap();
+        assert!(drcode.is_enabled());
+        drcode.capture_message("Test message", Level::Info);
+    }
+
+    #[test]
+    fn test_with_panic_hook() {
+        let drcode = DrCode::new(Config::new("test_key", "test_project"))
+            .unwrap()
+            .with_panic_hook(PanicHook::new().call_previous(false));
+        assert!(drcode.is_enabled());
+        assert!(crate::panic::is_installed());
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_config() {
+        assert_eq!(
+            DrCode::new(Config::new("", "test_project")).err(),
+            Some(DrCodeError::MissingField("public_key".to_string()))
+        );
+        assert!(matches!(
+            DrCode::new(Config::new("test_key", "test project")),
+            Err(DrCodeError::InvalidConfig(_))
+        ));
+    }
+}
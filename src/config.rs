@@ -0,0 +1,623 @@
+use crate::DrCodeError;
+use sentry::types::Dsn;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::Duration;
+
+pub use sentry::types::Scheme;
+
+/// The DrCode ingest host used when no `host` or `dsn` is configured.
+pub const DEFAULT_HOST: &str = "pulse.drcode.ai";
+
+/// Configuration for the DrCode Rust error reporting.
+///
+/// Events are sent to `pulse.drcode.ai` over HTTPS unless `scheme`, `host` or
+/// `port` are set, or a complete `dsn` overrides the endpoint altogether.
+///
+/// A configuration can also be loaded with [`Config::from_env`] and
+/// [`Config::from_file`]. Values are merged in this order, later sources
+/// taking precedence over earlier ones:
+///
+/// 1. the defaults,
+/// 2. a TOML or JSON config file,
+/// 3. `DRCODE_*` environment variables,
+/// 4. fields set in code on the returned `Config`.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub public_key: String,
+    pub project_id: String,
+    /// Scheme of the ingest endpoint. Defaults to `Scheme::Https`.
+    pub scheme: Option<Scheme>,
+    /// Host of the ingest endpoint. Defaults to [`DEFAULT_HOST`].
+    pub host: Option<String>,
+    /// Port of the ingest endpoint. Defaults to the default port of `scheme`.
+    pub port: Option<u16>,
+    /// A full DSN such as `https://key@relay.example.com:9000/42`.
+    ///
+    /// When set, `scheme`, `host` and `port` must be left unset, and
+    /// `public_key` and `project_id` may be empty or must match the DSN.
+    pub dsn: Option<String>,
+    /// The environment events are tagged with, such as `production`.
+    pub environment: Option<String>,
+    /// The release events are tagged with, such as `my-app@2.0.0`.
+    ///
+    /// Defaults to the `SENTRY_RELEASE` environment variable, or no release.
+    /// Pass your application's own version, for example
+    /// `concat!(env!("CARGO_PKG_NAME"), "@", env!("CARGO_PKG_VERSION"))`.
+    pub release: Option<String>,
+    /// The fraction of error events that are sent, between 0.0 and 1.0. Defaults to 1.0.
+    pub sample_rate: Option<f32>,
+    /// The fraction of transactions that are sent, between 0.0 and 1.0. Defaults to 0.0.
+    pub traces_sample_rate: Option<f32>,
+    /// The name of the server or host events are sent from. Defaults to the hostname.
+    pub server_name: Option<String>,
+    /// The maximum number of breadcrumbs kept per scope. Defaults to 100.
+    pub max_breadcrumbs: Option<usize>,
+    /// Print diagnostic information about the SDK to stderr.
+    pub debug: bool,
+    /// Attach a stacktrace to every captured message and error. Defaults to `true`.
+    pub attach_stacktrace: Option<bool>,
+    /// How long to wait for pending events to be sent on shutdown, when the
+    /// panic hook reports a panic and in [`exit`](crate::exit). Defaults to 2 seconds.
+    pub shutdown_timeout: Option<Duration>,
+    /// The maximum number of errors reported from one `source()` chain.
+    /// Defaults to [`DEFAULT_MAX_ERROR_CHAIN_DEPTH`](crate::DEFAULT_MAX_ERROR_CHAIN_DEPTH).
+    pub max_error_chain_depth: Option<usize>,
+}
+
+/// Environment variable naming a config file that [`Config::from_env`] loads first.
+pub const CONFIG_FILE_ENV: &str = "DRCODE_CONFIG_FILE";
+
+impl Config {
+    /// Create a configuration for the default DrCode endpoint.
+    pub fn new(public_key: impl Into<String>, project_id: impl Into<String>) -> Self {
+        Config {
+            public_key: public_key.into(),
+            project_id: project_id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Load a configuration from `DRCODE_*` environment variables.
+    ///
+    /// If `DRCODE_CONFIG_FILE` is set, that file is loaded first and the
+    /// environment variables override its values.
+    ///
+    /// | Variable                       | Field                   |
+    /// |------------------------------|----------------------|
+    /// | `DRCODE_PUBLIC_KEY`            | `public_key`            |
+    /// | `DRCODE_PROJECT_ID`            | `project_id`            |
+    /// | `DRCODE_DSN`                   | `dsn`                   |
+    /// | `DRCODE_SCHEME`                | `scheme`                |
+    /// | `DRCODE_HOST`                  | `host`                  |
+    /// | `DRCODE_PORT`                  | `port`                  |
+    /// | `DRCODE_ENVIRONMENT`           | `environment`           |
+    /// | `DRCODE_RELEASE`               | `release`               |
+    /// | `DRCODE_SAMPLE_RATE`           | `sample_rate`           |
+    /// | `DRCODE_TRACES_SAMPLE_RATE`    | `traces_sample_rate`    |
+    /// | `DRCODE_SERVER_NAME`           | `server_name`           |
+    /// | `DRCODE_MAX_BREADCRUMBS`       | `max_breadcrumbs`       |
+    /// | `DRCODE_DEBUG`                 | `debug`                 |
+    /// | `DRCODE_ATTACH_STACKTRACE`     | `attach_stacktrace`     |
+    /// | `DRCODE_SHUTDOWN_TIMEOUT_MS`   | `shutdown_timeout`      |
+    /// | `DRCODE_MAX_ERROR_CHAIN_DEPTH` | `max_error_chain_depth` |
+    ///
+    /// Boolean variables accept `true` or `false`.
+    pub fn from_env() -> Result<Config, DrCodeError> {
+        Config::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Load a configuration from a TOML or JSON file, chosen by its extension.
+    ///
+    /// The file uses the field names of `Config` as keys, except for
+    /// `shutdown_timeout`, which is read from `shutdown_timeout_ms`. `DRCODE_*`
+    /// environment variables override values from the file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Config, DrCodeError> {
+        let mut config = Config::default();
+        ConfigLayer::from_file(path.as_ref())?.apply(&mut config)?;
+        ConfigLayer::from_lookup(|name| std::env::var(name).ok())?.apply(&mut config)?;
+        Ok(config)
+    }
+
+    pub(crate) fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, DrCodeError> {
+        let mut config = Config::default();
+        if let Some(path) = lookup(CONFIG_FILE_ENV) {
+            ConfigLayer::from_file(Path::new(&path))?.apply(&mut config)?;
+        }
+        ConfigLayer::from_lookup(lookup)?.apply(&mut config)?;
+        Ok(config)
+    }
+
+    /// Build and validate the DSN events will be sent to.
+    pub fn dsn(&self) -> Result<Dsn, DrCodeError> {
+        match &self.dsn {
+            Some(dsn) => self.dsn_from_override(dsn),
+            None => self.dsn_from_parts(),
+        }
+    }
+
+    fn dsn_from_override(&self, dsn: &str) -> Result<Dsn, DrCodeError> {
+        if self.scheme.is_some() || self.host.is_some() || self.port.is_some() {
+            return Err(DrCodeError::InvalidConfig(
+                "`dsn` cannot be combined with `scheme`, `host` or `port`".to_string(),
+            ));
+        }
+
+        let dsn: Dsn = dsn
+            .parse()
+            .map_err(|e| DrCodeError::InvalidConfig(format!("`dsn` is not a valid DSN: {}", e)))?;
+
+        if !self.public_key.is_empty() && self.public_key != dsn.public_key() {
+            return Err(DrCodeError::InvalidConfig(
+                "`public_key` does not match the key in `dsn`".to_string(),
+            ));
+        }
+        if !self.project_id.is_empty() && self.project_id != dsn.project_id().value() {
+            return Err(DrCodeError::InvalidConfig(
+                "`project_id` does not match the project in `dsn`".to_string(),
+            ));
+        }
+
+        Ok(dsn)
+    }
+
+    /// Build the client options used to initialize the underlying Sentry client.
+    pub(crate) fn client_options(&self) -> Result<sentry::ClientOptions, DrCodeError> {
+        let mut options = sentry::ClientOptions {
+            dsn: Some(self.dsn()?),
+            release: self.release.clone().map(Cow::Owned),
+            environment: self.environment.clone().map(Cow::Owned),
+            server_name: self.server_name.clone().map(Cow::Owned),
+            debug: self.debug,
+            attach_stacktrace: self.attach_stacktrace.unwrap_or(true),
+            ..Default::default()
+        };
+        if let Some(max_breadcrumbs) = self.max_breadcrumbs {
+            options.max_breadcrumbs = max_breadcrumbs;
+        }
+        if self.max_error_chain_depth == Some(0) {
+            return Err(DrCodeError::InvalidConfig(
+                "`max_error_chain_depth` must be at least 1".to_string(),
+            ));
+        }
+        if let Some(shutdown_timeout) = self.shutdown_timeout {
+            options.shutdown_timeout = shutdown_timeout;
+        }
+        if let Some(rate) = self.sample_rate {
+            options.sample_rate = validate_rate("sample_rate", rate)?;
+        }
+        if let Some(rate) = self.traces_sample_rate {
+            options.traces_sample_rate = validate_rate("traces_sample_rate", rate)?;
+        }
+        Ok(options)
+    }
+
+    fn dsn_from_parts(&self) -> Result<Dsn, DrCodeError> {
+        if self.public_key.is_empty() {
+            return Err(DrCodeError::MissingField("public_key".to_string()));
+        }
+        validate_project_id(&self.project_id)?;
+
+        let scheme = self.scheme.unwrap_or(Scheme::Https);
+        let host = self.host.as_deref().unwrap_or(DEFAULT_HOST);
+        validate_host(host)?;
+
+        let port = match self.port {
+            Some(0) => {
+                return Err(DrCodeError::InvalidConfig(
+                    "`port` must not be 0".to_string(),
+                ))
+            }
+            Some(port) => port,
+            None => scheme.default_port(),
+        };
+
+        let dsn = format!(
+            "{}://{}@{}:{}/{}",
+            scheme, self.public_key, host, port, self.project_id
+        );
+        dsn.parse().map_err(|e| {
+            DrCodeError::InvalidConfig(format!(
+                "could not build a DSN from the configuration: {}",
+                e
+            ))
+        })
+    }
+}
+
+/// One source of configuration values; unset values leave the target untouched.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    public_key: Option<String>,
+    project_id: Option<String>,
+    scheme: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    dsn: Option<String>,
+    environment: Option<String>,
+    release: Option<String>,
+    sample_rate: Option<f32>,
+    traces_sample_rate: Option<f32>,
+    server_name: Option<String>,
+    max_breadcrumbs: Option<usize>,
+    debug: Option<bool>,
+    attach_stacktrace: Option<bool>,
+    shutdown_timeout_ms: Option<u64>,
+    max_error_chain_depth: Option<usize>,
+}
+
+impl ConfigLayer {
+    fn from_file(path: &Path) -> Result<ConfigLayer, DrCodeError> {
+        let error = |msg: String| DrCodeError::ConfigFile(format!("{}: {}", path.display(), msg));
+
+        let contents = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| error(e.to_string())),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| error(e.to_string())),
+            _ => Err(error(
+                "unsupported file type, expected a .toml or .json file".to_string(),
+            )),
+        }
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<ConfigLayer, DrCodeError> {
+        fn parse<T: std::str::FromStr>(
+            name: &str,
+            value: Option<String>,
+        ) -> Result<Option<T>, DrCodeError>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .map(|v| {
+                    v.trim().parse().map_err(|e| {
+                        DrCodeError::InvalidConfig(format!("`{}` is invalid: {}", name, e))
+                    })
+                })
+                .transpose()
+        }
+
+        Ok(ConfigLayer {
+            public_key: lookup("DRCODE_PUBLIC_KEY"),
+            project_id: lookup("DRCODE_PROJECT_ID"),
+            scheme: lookup("DRCODE_SCHEME"),
+            host: lookup("DRCODE_HOST"),
+            port: parse("DRCODE_PORT", lookup("DRCODE_PORT"))?,
+            dsn: lookup("DRCODE_DSN"),
+            environment: lookup("DRCODE_ENVIRONMENT"),
+            release: lookup("DRCODE_RELEASE"),
+            sample_rate: parse("DRCODE_SAMPLE_RATE", lookup("DRCODE_SAMPLE_RATE"))?,
+            traces_sample_rate: parse(
+                "DRCODE_TRACES_SAMPLE_RATE",
+                lookup("DRCODE_TRACES_SAMPLE_RATE"),
+            )?,
+            server_name: lookup("DRCODE_SERVER_NAME"),
+            max_breadcrumbs: parse("DRCODE_MAX_BREADCRUMBS", lookup("DRCODE_MAX_BREADCRUMBS"))?,
+            debug: parse("DRCODE_DEBUG", lookup("DRCODE_DEBUG"))?,
+            attach_stacktrace: parse(
+                "DRCODE_ATTACH_STACKTRACE",
+                lookup("DRCODE_ATTACH_STACKTRACE"),
+            )?,
+            shutdown_timeout_ms: parse(
+                "DRCODE_SHUTDOWN_TIMEOUT_MS",
+                lookup("DRCODE_SHUTDOWN_TIMEOUT_MS"),
+            )?,
+            max_error_chain_depth: parse(
+                "DRCODE_MAX_ERROR_CHAIN_DEPTH",
+                lookup("DRCODE_MAX_ERROR_CHAIN_DEPTH"),
+            )?,
+        })
+    }
+
+    fn apply(self, config: &mut Config) -> Result<(), DrCodeError> {
+        if let Some(scheme) = self.scheme {
+            config.scheme = Some(match scheme.to_ascii_lowercase().as_str() {
+                "http" => Scheme::Http,
+                "https" => Scheme::Https,
+                _ => {
+                    return Err(DrCodeError::InvalidConfig(format!(
+                        "`scheme` must be \"http\" or \"https\", got {:?}",
+                        scheme
+                    )))
+                }
+            });
+        }
+        if let Some(public_key) = self.public_key {
+            config.public_key = public_key;
+        }
+        if let Some(project_id) = self.project_id {
+            config.project_id = project_id;
+        }
+        if self.host.is_some() {
+            config.host = self.host;
+        }
+        if self.port.is_some() {
+            config.port = self.port;
+        }
+        if self.dsn.is_some() {
+            config.dsn = self.dsn;
+        }
+        if self.environment.is_some() {
+            config.environment = self.environment;
+        }
+        if self.release.is_some() {
+            config.release = self.release;
+        }
+        if self.sample_rate.is_some() {
+            config.sample_rate = self.sample_rate;
+        }
+        if self.traces_sample_rate.is_some() {
+            config.traces_sample_rate = self.traces_sample_rate;
+        }
+        if self.server_name.is_some() {
+            config.server_name = self.server_name;
+        }
+        if self.max_breadcrumbs.is_some() {
+            config.max_breadcrumbs = self.max_breadcrumbs;
+        }
+        if let Some(debug) = self.debug {
+            config.debug = debug;
+        }
+        if self.attach_stacktrace.is_some() {
+            config.attach_stacktrace = self.attach_stacktrace;
+        }
+        if let Some(ms) = self.shutdown_timeout_ms {
+            config.shutdown_timeout = Some(Duration::from_millis(ms));
+        }
+        if self.max_error_chain_depth.is_some() {
+            config.max_error_chain_depth = self.max_error_chain_depth;
+        }
+        Ok(())
+    }
+}
+
+fn validate_rate(field: &str, rate: f32) -> Result<f32, DrCodeError> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(DrCodeError::InvalidConfig(format!(
+            "`{}` must be between 0.0 and 1.0, got {}",
+            field, rate
+        )));
+    }
+    Ok(rate)
+}
+
+fn validate_project_id(project_id: &str) -> Result<(), DrCodeError> {
+    if project_id.is_empty() {
+        return Err(DrCodeError::MissingField("project_id".to_string()));
+    }
+    if !project_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(DrCodeError::InvalidConfig(format!(
+            "`project_id` may only contain letters, digits, '-' and '_', got {:?}",
+            project_id
+        )));
+    }
+    Ok(())
+}
+
+fn validate_host(host: &str) -> Result<(), DrCodeError> {
+    let is_ipv6 = host.starts_with('[') && host.ends_with(']');
+    let invalid = host.is_empty()
+        || host
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '/' | '@' | '?' | '#'))
+        || (!is_ipv6 && host.contains(':'));
+
+    if invalid {
+        return Err(DrCodeError::InvalidConfig(format!(
+            "`host` must be a bare host name without scheme, port or path, got {:?}",
+            host
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_default_endpoint() {
+        let dsn = Config::new("key", "42").dsn().unwrap();
+        assert_eq!(dsn.scheme(), Scheme::Https);
+        assert_eq!(dsn.host(), DEFAULT_HOST);
+        assert_eq!(dsn.port(), 443);
+        assert_eq!(dsn.project_id().value(), "42");
+    }
+
+    #[test]
+    fn test_custom_endpoint() {
+        let config = Config {
+            scheme: Some(Scheme::Http),
+            host: Some("localhost".to_string()),
+            port: Some(9000),
+            ..Config::new("key", "42")
+        };
+        let dsn = config.dsn().unwrap();
+        assert_eq!(dsn.scheme(), Scheme::Http);
+        assert_eq!(dsn.host(), "localhost");
+        assert_eq!(dsn.port(), 9000);
+
+        let config = Config {
+            dsn: Some("https://other@relay.example.com:8443/7".to_string()),
+            ..Default::default()
+        };
+        let dsn = config.dsn().unwrap();
+        assert_eq!(dsn.host(), "relay.example.com");
+        assert_eq!(dsn.public_key(), "other");
+    }
+
+    #[test]
+    fn test_invalid_endpoint() {
+        let with_host = Config {
+            dsn: Some("https://key@relay.example.com/42".to_string()),
+            host: Some("localhost".to_string()),
+            ..Default::default()
+        };
+        assert!(with_host.dsn().is_err());
+
+        let mismatched = Config {
+            dsn: Some("https://key@relay.example.com/42".to_string()),
+            ..Config::new("other", "42")
+        };
+        assert!(mismatched.dsn().is_err());
+
+        let host_with_port = Config {
+            host: Some("localhost:9000".to_string()),
+            ..Config::new("key", "42")
+        };
+        assert!(host_with_port.dsn().is_err());
+
+        let zero_port = Config {
+            port: Some(0),
+            ..Config::new("key", "42")
+        };
+        assert!(zero_port.dsn().is_err());
+
+        assert_eq!(
+            Config::new("", "42").dsn().unwrap_err(),
+            DrCodeError::MissingField("public_key".to_string())
+        );
+        assert_eq!(
+            Config::new("key", "").dsn().unwrap_err(),
+            DrCodeError::MissingField("project_id".to_string())
+        );
+        assert!(matches!(
+            Config::new("key", "my/project").dsn(),
+            Err(DrCodeError::InvalidConfig(_))
+        ));
+
+        let bad_dsn = Config {
+            dsn: Some("ftp://key@relay.example.com/42".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_dsn.dsn().is_err());
+    }
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn write_temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("drcode-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_env_vars() {
+        let config = Config::from_lookup(lookup(&[
+            ("DRCODE_PUBLIC_KEY", "key"),
+            ("DRCODE_PROJECT_ID", "42"),
+            ("DRCODE_SCHEME", "HTTP"),
+            ("DRCODE_PORT", "9000"),
+            ("DRCODE_ENVIRONMENT", "staging"),
+            ("DRCODE_TRACES_SAMPLE_RATE", "0.25"),
+        ]))
+        .unwrap();
+        assert_eq!(config.public_key, "key");
+        assert_eq!(config.scheme, Some(Scheme::Http));
+        assert_eq!(config.port, Some(9000));
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+        assert_eq!(config.traces_sample_rate, Some(0.25));
+
+        assert!(Config::from_lookup(lookup(&[("DRCODE_PORT", "http")])).is_err());
+        assert!(Config::from_lookup(lookup(&[("DRCODE_SCHEME", "ftp")])).is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let path = write_temp_file(
+            "config.toml",
+            "public_key = \"file_key\"\nproject_id = \"42\"\nenvironment = \"production\"\n",
+        );
+        let config = Config::from_lookup(lookup(&[
+            (CONFIG_FILE_ENV, path.to_str().unwrap()),
+            ("DRCODE_ENVIRONMENT", "staging"),
+        ]))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.public_key, "file_key");
+        assert_eq!(config.project_id, "42");
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn test_from_json_file() {
+        let path = write_temp_file(
+            "config.json",
+            r#"{"dsn": "https://key@relay.example.com/42", "sample_rate": 0.5}"#,
+        );
+        let layer = ConfigLayer::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut config = Config::default();
+        layer.unwrap().apply(&mut config).unwrap();
+        assert_eq!(config.sample_rate, Some(0.5));
+        assert_eq!(config.dsn().unwrap().host(), "relay.example.com");
+
+        let path = write_temp_file("config.json.bak", "{}");
+        assert!(matches!(
+            ConfigLayer::from_file(&path),
+            Err(DrCodeError::ConfigFile(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_client_options() {
+        let options = Config::new("key", "42").client_options().unwrap();
+        assert!(options.attach_stacktrace);
+        assert!(!options.debug);
+        assert_eq!(options.sample_rate, 1.0);
+        assert_eq!(options.release, None);
+
+        let config = Config::from_lookup(lookup(&[
+            ("DRCODE_PUBLIC_KEY", "key"),
+            ("DRCODE_PROJECT_ID", "42"),
+            ("DRCODE_SERVER_NAME", "worker-1"),
+            ("DRCODE_MAX_BREADCRUMBS", "20"),
+            ("DRCODE_DEBUG", "true"),
+            ("DRCODE_ATTACH_STACKTRACE", "false"),
+            ("DRCODE_SAMPLE_RATE", "0.5"),
+            ("DRCODE_RELEASE", "my-app@2.0.0"),
+            ("DRCODE_SHUTDOWN_TIMEOUT_MS", "500"),
+        ]))
+        .unwrap();
+        let options = config.client_options().unwrap();
+        assert_eq!(options.server_name.as_deref(), Some("worker-1"));
+        assert_eq!(options.max_breadcrumbs, 20);
+        assert!(options.debug);
+        assert!(!options.attach_stacktrace);
+        assert_eq!(options.sample_rate, 0.5);
+        assert_eq!(options.release.as_deref(), Some("my-app@2.0.0"));
+        assert_eq!(options.shutdown_timeout, Duration::from_millis(500));
+
+        assert!(Config::from_lookup(lookup(&[("DRCODE_DEBUG", "maybe")])).is_err());
+    }
+
+    #[test]
+    fn test_invalid_sample_rate() {
+        let config = Config {
+            sample_rate: Some(1.5),
+            ..Config::new("key", "42")
+        };
+        assert!(matches!(
+            config.client_options(),
+            Err(DrCodeError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+/// Errors returned while configuring or initializing DrCode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrCodeError {
+    /// A required configuration field is missing or empty.
+    MissingField(String),
+    /// The configuration contains an invalid value or an invalid combination of values.
+    InvalidConfig(String),
+    /// A config file could not be read or parsed.
+    ConfigFile(String),
+    /// The underlying client could not be initialized.
+    InitializationError(String),
+}
+
+impl fmt::Display for DrCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrCodeError::MissingField(field) => write!(f, "missing required field `{}`", field),
+            DrCodeError::InvalidConfig(msg) => write!(f, "invalid configuration: {}", msg),
+            DrCodeError::ConfigFile(msg) => write!(f, "invalid config file: {}", msg),
+            DrCodeError::InitializationError(msg) => write!(f, "initialization failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DrCodeError {}
+
+/// Why a task run by [`try_run_with_error_reporting`](crate::try_run_with_error_reporting) failed.
+#[derive(Debug)]
+pub enum TaskError<E> {
+    /// The task returned an error.
+    Error(E),
+    /// The task panicked.
+    Panic {
+        /// The panic message.
+        message: String,
+        /// Where the task panicked, as `file:line:column`. Only known when the
+        /// DrCode panic hook is installed.
+        location: Option<String>,
+    },
+    /// The task was cancelled before it completed, usually because the runtime shut down.
+    Cancelled,
+}
+
+impl<E: fmt::Display> fmt::Display for TaskError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Error(e) => e.fmt(f),
+            TaskError::Panic {
+                message,
+                location: Some(location),
+            } => write!(f, "task panicked at {}: {}", location, message),
+            TaskError::Panic { message, .. } => write!(f, "task panicked: {}", message),
+            TaskError::Cancelled => f.write_str("task was cancelled"),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for TaskError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TaskError::Error(e) => e.source(),
+            _ => None,
+        }
+    }
+}
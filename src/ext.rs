@@ -0,0 +1,84 @@
+use crate::{report_error, Level};
+use sentry::Scope;
+
+/// Report the error of a `Result` inline and pass the `Result` through unchanged.
+///
+/// ```no_run
+/// use drcode_rust::ReportExt;
+///
+/// fn read_config() -> std::io::Result<String> {
+///     let contents = std::fs::read_to_string("config.toml").report()?;
+///     Ok(contents)
+/// }
+/// ```
+pub trait ReportExt: Sized {
+    /// Report the error, if any.
+    fn report(self) -> Self;
+
+    /// Report the error, if any, with the scope adjusted by `f` for this event only.
+    fn report_with<F: FnOnce(&mut Scope)>(self, f: F) -> Self;
+
+    /// Report the error, if any, with the given severity level.
+    fn report_at(self, level: Level) -> Self {
+        self.report_with(|scope| scope.set_level(Some(level)))
+    }
+}
+
+impl<T, E: std::error::Error> ReportExt for Result<T, E> {
+    fn report(self) -> Self {
+        if let Err(e) = &self {
+            report_error(e);
+        }
+        self
+    }
+
+    fn report_with<F: FnOnce(&mut Scope)>(self, f: F) -> Self {
+        if let Err(e) = &self {
+            sentry::with_scope(f, || report_error(e));
+        }
+        self
+    }
+}
+
+/// Report a missing value of an `Option` inline and pass the `Option` through unchanged.
+pub trait ReportNoneExt: Sized {
+    /// Report `message` at error level if the value is `None`.
+    fn report_none(self, message: &str) -> Self;
+}
+
+impl<T> ReportNoneExt for Option<T> {
+    fn report_none(self, message: &str) -> Self {
+        if self.is_none() {
+            sentry::capture_message(message, Level::Error);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_report_ext() {
+        let events = sentry::test::with_captured_events(|| {
+            assert_eq!(Ok::<_, io::Error>(1).report().unwrap(), 1);
+            assert!(Err::<(), _>(io::Error::other("plain")).report().is_err());
+            let _ = Err::<(), _>(io::Error::other("warning")).report_at(Level::Warning);
+            let _ = Err::<(), _>(io::Error::other("tagged"))
+                .report_with(|scope| scope.set_tag("job", "import"));
+            assert_eq!(Some(1).report_none("missing"), Some(1));
+            assert_eq!(None::<u32>.report_none("missing"), None);
+        });
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].exception[0].value.as_deref(), Some("plain"));
+        assert_eq!(events[1].level, Level::Warning);
+        assert_eq!(
+            events[2].tags.get("job").map(String::as_str),
+            Some("import")
+        );
+        assert_eq!(events[3].message.as_deref(), Some("missing"));
+    }
+}
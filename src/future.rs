@@ -0,0 +1,149 @@
+use crate::{panic, report_error};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Report the errors and panics of a future in place.
+///
+/// Unlike [`run_with_error_reporting`](crate::run_with_error_reporting), the
+/// future is polled on the current task rather than spawned, so it may borrow
+/// local state, need not be `Send` and can run on a `LocalSet`.
+///
+/// ```no_run
+/// use drcode_rust::FutureExt;
+///
+/// async fn handle(name: &str) -> std::io::Result<String> {
+///     tokio::fs::read_to_string(name).await
+/// }
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let name = String::from("orders.json");
+/// let orders = handle(&name).report_errors().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait FutureExt<T, E>: Future<Output = Result<T, E>> + Sized {
+    /// Report the error, if the future resolves to one, and any panic while
+    /// polling it. Panics are reported and then resumed.
+    fn report_errors(self) -> ReportErrors<Self> {
+        ReportErrors {
+            future: CatchUnwind::new(self),
+        }
+    }
+}
+
+impl<F, T, E> FutureExt<T, E> for F where F: Future<Output = Result<T, E>> {}
+
+/// Future returned by [`FutureExt::report_errors`].
+#[must_use = "futures do nothing unless polled"]
+pub struct ReportErrors<F> {
+    future: CatchUnwind<F>,
+}
+
+impl<F, T, E> Future for ReportErrors<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: std::error::Error,
+{
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.future).poll(cx) {
+            Poll::Ready(Ok(Err(e))) => {
+                report_error(&e);
+                Poll::Ready(Err(e))
+            }
+            Poll::Ready(Ok(output)) => Poll::Ready(output),
+            Poll::Ready(Err(payload)) => {
+                let location = panic::report_caught_panic(payload.as_ref());
+                panic::resume_reported(payload, location)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Resolves to the output of a future, or to the payload of a panic while polling it.
+pub(crate) struct CatchUnwind<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> CatchUnwind<F> {
+    pub(crate) fn new(future: F) -> Self {
+        CatchUnwind {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.future.as_mut();
+        match panic::catch_unwind(|| future.poll(cx)) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::AssertUnwindSafe;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_report_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let local = tokio::task::LocalSet::new();
+        let name = String::from("local");
+
+        let events = sentry::test::with_captured_events(|| {
+            local.block_on(&runtime, async {
+                let shared = Rc::new(name.as_str());
+                let ok = async { Ok::<_, std::io::Error>(shared.len()) };
+                assert_eq!(ok.report_errors().await.unwrap(), 5);
+
+                let err = async {
+                    tokio::task::yield_now().await;
+                    Err::<(), _>(std::io::Error::other(format!("{} failed", shared)))
+                };
+                assert!(err.report_errors().await.is_err());
+            });
+        });
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].exception[0].value.as_deref(),
+            Some("local failed")
+        );
+    }
+
+    #[test]
+    fn test_report_errors_panic() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                runtime.block_on(
+                    async {
+                        panic!("panicked in place");
+                        #[allow(unreachable_code)]
+                        Ok::<(), std::io::Error>(())
+                    }
+                    .report_errors(),
+                )
+            }));
+            assert!(result.is_err());
+        });
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].exception[0].value.as_deref(),
+            Some("panicked in place")
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Middleware for actix-web servers.
+//!
+//! ```no_run
+//! use actix_web::{web, App, HttpServer};
+//! use drcode_rust::actix::DrCode;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! HttpServer::new(|| {
+//!     App::new()
+//!         .wrap(DrCode::new().allow_header("x-request-id"))
+//!         .route("/users/{id}", web::get().to(|| async { "user" }))
+//! })
+//! .bind(("127.0.0.1", 8080))?
+//! .run()
+//! .await
+//! # }
+//! ```
+
+use super::server::{request_data, ServerRequest, EVENT_ID_HEADER};
+use crate::future::CatchUnwind;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use sentry::SentryFutureExt;
+use std::error::Error as StdError;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// An actix-web middleware that reports failed requests and times each request.
+///
+/// Requests are handled as by the `tower` middleware, each with its own hub
+/// and `http.server` transaction, and with the same request data and header
+/// filtering. Transactions are named after the method and the pattern of the
+/// matched resource, such as `/users/{id}`, and responses with a 5xx status
+/// are reported with the `ResponseError` that produced them if any.
+///
+/// Unlike [`run_with_error_reporting`](crate::run_with_error_reporting), the
+/// request is handled in place on the worker's single-threaded runtime, so
+/// handlers do not need to be `Send`.
+#[derive(Clone, Default)]
+pub struct DrCode {
+    allowed_headers: Rc<Vec<String>>,
+}
+
+impl DrCode {
+    /// Create a middleware that only sends the values of the default headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also send the value of the header `name`.
+    pub fn allow_header(mut self, name: &str) -> Self {
+        Rc::make_mut(&mut self.allowed_headers).push(name.to_ascii_lowercase());
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for DrCode
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = DrCodeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<DrCodeMiddleware<S>, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(DrCodeMiddleware {
+            service,
+            allowed_headers: self.allowed_headers.clone(),
+        }))
+    }
+}
+
+/// Service created by the [`DrCode`] middleware.
+pub struct DrCodeMiddleware<S> {
+    service: S,
+    allowed_headers: Rc<Vec<String>>,
+}
+
+impl<S, B> Service<ServiceRequest> for DrCodeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| request.path().to_string());
+        let name = format!("{} {}", request.method(), route);
+        let url = {
+            let info = request.connection_info();
+            format!("{}://{}{}", info.scheme(), info.host(), request.path())
+        };
+        let data = request_data(
+            request.method().as_str(),
+            &url,
+            Some(request.query_string()).filter(|query| !query.is_empty()),
+            request
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+            &self.allowed_headers,
+        );
+
+        let (mut request, future) =
+            ServerRequest::start(name, data, || CatchUnwind::new(self.service.call(request)));
+
+        Box::pin(async move {
+            match future.bind_hub(request.hub().clone()).await {
+                Ok(Ok(mut response)) => {
+                    let status = response.status();
+                    let error = response.response().error().map(|e| e as &dyn StdError);
+                    let id = request.respond(status.as_u16(), status.canonical_reason(), error);
+                    if let Some(id) = id {
+                        let value = HeaderValue::from_str(&id.to_string()).unwrap();
+                        response
+                            .headers_mut()
+                            .insert(HeaderName::from_static(EVENT_ID_HEADER), value);
+                    }
+                    Ok(response)
+                }
+                Ok(Err(error)) => {
+                    let status = error.as_response_error().status_code();
+                    request.respond(status.as_u16(), status.canonical_reason(), Some(&error));
+                    Err(error)
+                }
+                Err(payload) => request.panicked(payload),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::capture_items;
+    use crate::SpanStatus;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse, ResponseError};
+    use sentry::protocol::{EnvelopeItem, Value};
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct StoreUnavailable;
+
+    impl fmt::Display for StoreUnavailable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("store unavailable")
+        }
+    }
+
+    impl ResponseError for StoreUnavailable {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+
+    async fn show_user() -> HttpResponse {
+        HttpResponse::Ok().body("user")
+    }
+
+    async fn export_orders() -> HttpResponse {
+        std::future::pending().await
+    }
+
+    async fn list_orders() -> Result<HttpResponse, StoreUnavailable> {
+        sentry::configure_scope(|scope| scope.set_tag("handler", "orders"));
+        tokio::task::yield_now().await;
+        Err(StoreUnavailable)
+    }
+
+    #[test]
+    fn test_middleware() {
+        let mut event_id = None;
+        let items = capture_items(1.0, || {
+            actix_web::rt::System::new().block_on(async {
+                let app = init_service(
+                    App::new()
+                        .wrap(DrCode::new().allow_header("X-Request-Id"))
+                        .route("/orders", web::get().to(list_orders))
+                        .route("/users/{id}", web::get().to(show_user)),
+                )
+                .await;
+
+                let request = TestRequest::get()
+                    .uri("/orders?page=2")
+                    .insert_header(("authorization", "Bearer secret"))
+                    .insert_header(("x-upstream-token", "abc"))
+                    .insert_header(("x-request-id", "42"))
+                    .insert_header(("user-agent", "curl/8.0"))
+                    .to_request();
+                let response = call_service(&app, request).await;
+                assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+                let header = response.headers().get(EVENT_ID_HEADER).unwrap();
+                event_id = Some(header.to_str().unwrap().to_string());
+
+                let request = TestRequest::get().uri("/users/42").to_request();
+                let response = call_service(&app, request).await;
+                assert!(!response.headers().contains_key(EVENT_ID_HEADER));
+            });
+        });
+
+        assert_eq!(items.len(), 3);
+        let (event, orders, user) = match &items[..] {
+            [EnvelopeItem::Event(event), EnvelopeItem::Transaction(orders), EnvelopeItem::Transaction(user)] => {
+                (event, orders, user)
+            }
+            other => panic!("unexpected envelope items: {:?}", other),
+        };
+
+        assert_eq!(Some(event.event_id.to_string()), event_id);
+        assert_eq!(
+            event.exception[0].value.as_deref(),
+            Some("store unavailable")
+        );
+        assert_eq!(event.transaction.as_deref(), Some("GET /orders"));
+        assert_eq!(event.tags["handler"], "orders");
+        let request = event.request.as_ref().unwrap();
+        assert_eq!(request.query_string.as_deref(), Some("page=2"));
+        assert_eq!(request.headers["authorization"], "[Filtered]");
+        assert_eq!(request.headers["x-upstream-token"], "[Filtered]");
+        assert_eq!(request.headers["x-request-id"], "42");
+        assert_eq!(request.headers["user-agent"], "curl/8.0");
+
+        assert_eq!(orders.name.as_deref(), Some("GET /orders"));
+        assert_eq!(orders.extra["http.response.status_code"], Value::from(503));
+        assert_eq!(user.name.as_deref(), Some("GET /users/{id}"));
+        assert_eq!(
+            user.request
+                .as_ref()
+                .unwrap()
+                .url
+                .as_ref()
+                .unwrap()
+                .as_str(),
+            "http://localhost:8080/users/42"
+        );
+        assert!(!user.tags.contains_key("handler"));
+    }
+
+    #[test]
+    fn test_middleware_cancelled() {
+        let items = capture_items(1.0, || {
+            actix_web::rt::System::new().block_on(async {
+                let app = init_service(
+                    App::new()
+                        .wrap(DrCode::new())
+                        .route("/export", web::get().to(export_orders)),
+                )
+                .await;
+
+                let request = TestRequest::get().uri("/export").to_request();
+                let mut response = Box::pin(app.call(request));
+                std::future::poll_fn(|cx| {
+                    assert!(response.as_mut().poll(cx).is_pending());
+                    std::task::Poll::Ready(())
+                })
+                .await;
+            });
+        });
+
+        assert_eq!(items.len(), 1);
+        let transaction = match &items[0] {
+            EnvelopeItem::Transaction(transaction) => transaction,
+            other => panic!("unexpected envelope item: {:?}", other),
+        };
+        assert_eq!(transaction.name.as_deref(), Some("GET /export"));
+        let status = match transaction.contexts.get("trace") {
+            Some(sentry::protocol::Context::Trace(trace)) => trace.status,
+            other => panic!("unexpected trace context: {:?}", other),
+        };
+        assert_eq!(status, Some(SpanStatus::Cancelled));
+    }
+}
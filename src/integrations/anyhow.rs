@@ -0,0 +1,65 @@
+use crate::{chain, Uuid};
+use sentry::protocol::Event;
+use std::backtrace::{Backtrace, BacktraceStatus};
+
+/// Report an `anyhow::Error` along with its chain of causes.
+///
+/// The backtrace captured by `anyhow` is attached to the outermost error.
+/// `anyhow` only captures backtraces when `RUST_BACKTRACE` or
+/// `RUST_LIB_BACKTRACE` is set.
+pub fn report_anyhow(error: &anyhow::Error) -> Uuid {
+    sentry::capture_event(event_from_anyhow(error))
+}
+
+pub(crate) fn event_from_anyhow(error: &anyhow::Error) -> Event<'static> {
+    let mut event = chain::event_from_error(&**error);
+    attach_captured_backtrace(&mut event, error.backtrace());
+    event
+}
+
+/// Attach `backtrace` to the outermost error if it was actually captured.
+fn attach_captured_backtrace(event: &mut Event<'static>, backtrace: &Backtrace) {
+    if backtrace.status() == BacktraceStatus::Captured {
+        super::attach_backtrace(event, &format!("{:#}", backtrace));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anyhow_event() {
+        let error = anyhow::anyhow!("disk full").context("loading config");
+
+        let events = sentry::test::with_captured_events(|| {
+            report_anyhow(&error);
+        });
+
+        assert_eq!(events.len(), 1);
+        let exceptions = &events[0].exception.values;
+        assert_eq!(exceptions.len(), 2);
+        assert_eq!(exceptions[0].value.as_deref(), Some("disk full"));
+        assert_eq!(exceptions[1].value.as_deref(), Some("loading config"));
+        assert_eq!(
+            exceptions[1].stacktrace.is_some(),
+            error.backtrace().status() == BacktraceStatus::Captured
+        );
+    }
+
+    #[test]
+    fn test_attach_captured_backtrace() {
+        let error = anyhow::anyhow!("disk full");
+
+        let mut event = chain::event_from_error(&*error);
+        attach_captured_backtrace(&mut event, &Backtrace::disabled());
+        assert!(event.exception[0].stacktrace.is_none());
+
+        attach_captured_backtrace(&mut event, &Backtrace::force_capture());
+        let stacktrace = event.exception[0].stacktrace.as_ref().unwrap();
+        assert!(stacktrace.frames.iter().any(|frame| frame
+            .function
+            .as_deref()
+            .is_some_and(|f| f.contains("test_attach_captured_backtrace"))));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{chain, Uuid};
+use sentry::protocol::Event;
+
+/// Report an `eyre::Report` along with its chain of causes.
+///
+/// `eyre` leaves capturing backtraces to its report handler. If the handler
+/// prints a `Backtrace:` or `Stack backtrace:` section in the `Debug` output
+/// of the report, as the default handler does on nightly, that backtrace is
+/// attached to the outermost error.
+pub fn report_eyre(report: &eyre::Report) -> Uuid {
+    sentry::capture_event(event_from_eyre(report))
+}
+
+pub(crate) fn event_from_eyre(report: &eyre::Report) -> Event<'static> {
+    let mut event = chain::event_from_error(&**report);
+    if let Some(backtrace) = backtrace_section(&format!("{:?}", report)) {
+        super::attach_backtrace(&mut event, backtrace);
+    }
+    event
+}
+
+/// Return the text following the backtrace heading of a report's `Debug` output.
+fn backtrace_section(debug: &str) -> Option<&str> {
+    let mut offset = 0;
+    for line in debug.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim().to_ascii_lowercase().ends_with("backtrace:") {
+            return Some(&debug[offset..]);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eyre_event() {
+        let report = eyre::eyre!("disk full").wrap_err("loading config");
+
+        let events = sentry::test::with_captured_events(|| {
+            report_eyre(&report);
+        });
+
+        assert_eq!(events.len(), 1);
+        let exceptions = &events[0].exception.values;
+        assert_eq!(exceptions.len(), 2);
+        assert_eq!(exceptions[0].value.as_deref(), Some("disk full"));
+        assert_eq!(exceptions[1].value.as_deref(), Some("loading config"));
+    }
+
+    #[test]
+    fn test_backtrace_section() {
+        let debug = "loading config\n\nCaused by:\n    disk full\n\nStack backtrace:\n   0: app::load\n             at ./src/main.rs:10:5\n";
+        let backtrace = backtrace_section(debug).unwrap();
+        assert!(backtrace.starts_with("   0: app::load"));
+
+        let mut event = chain::event_from_error(&*eyre::eyre!("disk full"));
+        crate::integrations::attach_backtrace(&mut event, backtrace);
+        let frames = &event.exception[0].stacktrace.as_ref().unwrap().frames;
+        assert_eq!(frames[0].function.as_deref(), Some("app::load"));
+        assert_eq!(frames[0].lineno, Some(10));
+
+        assert!(backtrace_section("disk full").is_none());
+    }
+}
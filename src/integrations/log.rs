@@ -0,0 +1,217 @@
+//! A `log` logger that forwards records to DrCode.
+//!
+//! The [`Logger`] wraps the logger the application already uses, such as
+//! `env_logger`, so records are still printed as before:
+//!
+//! ```ignore
+//! use log::LevelFilter;
+//!
+//! let env_logger = env_logger::Builder::from_default_env().build();
+//! let level = env_logger.filter();
+//! let logger = drcode_rust::log::Logger::new(env_logger).filter_module("hyper", LevelFilter::Warn);
+//! drcode_rust::log::init(logger, level).expect("a logger was already installed");
+//! ```
+
+use crate::{add_breadcrumb, Breadcrumb};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use sentry::protocol::Event;
+use sentry::Level;
+
+/// Install `logger` as the global logger.
+///
+/// `dest_level` is the most verbose level the wrapped logger prints; the
+/// global maximum level is raised as needed for the records DrCode forwards.
+/// Fails if a global logger was already installed.
+pub fn init<L>(logger: Logger<L>, dest_level: LevelFilter) -> Result<(), SetLoggerError>
+where
+    L: Log + 'static,
+{
+    let max_level = dest_level.max(logger.max_level());
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// A logger that forwards `log` records to DrCode and to a wrapped logger.
+///
+/// `Error` records are reported as DrCode events, and `Warn` and `Info`
+/// records are recorded as breadcrumbs. Every record is also passed on to the
+/// wrapped logger, which applies its own filters.
+pub struct Logger<L> {
+    dest: L,
+    default_level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl<L: Log> Logger<L> {
+    /// Wrap `dest`, forwarding records from `Info` up from every module.
+    pub fn new(dest: L) -> Self {
+        Self {
+            dest,
+            default_level: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Set the most verbose level forwarded from modules without a filter of
+    /// their own.
+    pub fn filter_level(mut self, level: LevelFilter) -> Self {
+        self.default_level = level;
+        self
+    }
+
+    /// Set the most verbose level forwarded from `module` and its submodules.
+    ///
+    /// Records are matched on their target, which is the module path unless
+    /// set explicitly. The filter of the longest matching module is used, and
+    /// `LevelFilter::Off` stops forwarding records from the module entirely.
+    pub fn filter_module(mut self, module: &str, level: LevelFilter) -> Self {
+        self.modules.retain(|(path, _)| path != module);
+        self.modules.push((module.to_string(), level));
+        self
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let modules = self.modules.iter().map(|(_, level)| *level);
+        modules
+            .fold(self.default_level, Ord::max)
+            .min(LevelFilter::Info)
+    }
+
+    fn forwards(&self, metadata: &Metadata<'_>) -> bool {
+        let target = metadata.target();
+        let level = self
+            .modules
+            .iter()
+            .filter(|(module, _)| is_within(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default_level, |(_, level)| *level);
+        metadata.level() <= level.min(LevelFilter::Info)
+    }
+}
+
+fn is_within(target: &str, module: &str) -> bool {
+    match target.strip_prefix(module) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+impl<L: Log> Log for Logger<L> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.forwards(metadata) || self.dest.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.forwards(record.metadata()) {
+            if record.level() == log::Level::Error {
+                sentry::capture_event(event_from_record(record));
+            } else {
+                add_breadcrumb(breadcrumb_from_record(record));
+            }
+        }
+        if self.dest.enabled(record.metadata()) {
+            self.dest.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.dest.flush();
+    }
+}
+
+fn level_from_record(record: &Record<'_>) -> Level {
+    match record.level() {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warning,
+        log::Level::Info => Level::Info,
+        log::Level::Debug | log::Level::Trace => Level::Debug,
+    }
+}
+
+fn event_from_record(record: &Record<'_>) -> Event<'static> {
+    Event {
+        logger: Some(record.target().to_string()),
+        message: Some(record.args().to_string()),
+        level: level_from_record(record),
+        ..Default::default()
+    }
+}
+
+fn breadcrumb_from_record(record: &Record<'_>) -> Breadcrumb {
+    Breadcrumb {
+        ty: "log".to_string(),
+        category: Some(record.target().to_string()),
+        message: Some(record.args().to_string()),
+        level: level_from_record(record),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Log for Recorder {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn log(logger: &impl Log, level: log::Level, target: &str, message: &str) {
+        logger.log(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(level)
+                .target(target)
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_logger() {
+        let logger = Logger::new(Recorder::default())
+            .filter_module("hyper", LevelFilter::Warn)
+            .filter_module("hyper::client", LevelFilter::Off);
+
+        let events = sentry::test::with_captured_events(|| {
+            log(&logger, log::Level::Info, "app::jobs", "job started");
+            log(&logger, log::Level::Debug, "app::jobs", "job state loaded");
+            log(&logger, log::Level::Info, "hyper::proto", "connected");
+            log(&logger, log::Level::Warn, "hyperx", "retrying");
+            log(&logger, log::Level::Error, "hyper::client", "reset");
+            log(&logger, log::Level::Error, "app::jobs", "job failed");
+        });
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message.as_deref(), Some("job failed"));
+        assert_eq!(events[0].logger.as_deref(), Some("app::jobs"));
+        assert_eq!(events[0].level, Level::Error);
+
+        let breadcrumbs: Vec<_> = events[0]
+            .breadcrumbs
+            .iter()
+            .map(|breadcrumb| (breadcrumb.message.as_deref().unwrap(), breadcrumb.level))
+            .collect();
+        assert_eq!(
+            breadcrumbs,
+            [("job started", Level::Info), ("retrying", Level::Warning)]
+        );
+
+        assert_eq!(
+            *logger.dest.0.lock().unwrap(),
+            ["retrying", "reset", "job failed"]
+        );
+        assert_eq!(logger.max_level(), LevelFilter::Info);
+    }
+}
@@ -0,0 +1,28 @@
+//! Integrations with other crates, each behind the cargo feature of the same name.
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "anyhow")]
+pub(crate) mod anyhow;
+#[cfg(feature = "eyre")]
+pub(crate) mod eyre;
+#[cfg(feature = "log")]
+pub mod log;
+#[cfg(any(feature = "actix", feature = "tower"))]
+mod server;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+
+#[cfg(any(feature = "anyhow", feature = "eyre"))]
+use sentry::protocol::Event;
+
+/// Parse a backtrace in the text format of `std::backtrace::Backtrace` and
+/// attach it to the outermost exception of `event`.
+#[cfg(any(feature = "anyhow", feature = "eyre"))]
+pub(crate) fn attach_backtrace(event: &mut Event<'static>, backtrace: &str) {
+    if let Some(exception) = event.exception.values.last_mut() {
+        exception.stacktrace = sentry::integrations::backtrace::parse_stacktrace(backtrace);
+    }
+}
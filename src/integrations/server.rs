@@ -0,0 +1,239 @@
+//! Request data and response handling shared by the HTTP server middleware.
+
+use crate::{panic, report_error, start_transaction, Level, SpanStatus, Transaction, Uuid};
+use sentry::protocol::{Event, Map, Request};
+use sentry::Hub;
+use std::any::Any;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Response header carrying the id of the event reported for a request.
+pub(crate) const EVENT_ID_HEADER: &str = "x-drcode-event-id";
+
+/// Headers whose values are sent, in addition to those allowed on the
+/// middleware.
+const ALLOWED_HEADERS: &[&str] = &[
+    "accept",
+    "content-length",
+    "content-type",
+    "host",
+    "referer",
+    "user-agent",
+];
+
+const FILTERED: &str = "[Filtered]";
+
+/// Build the request data attached to the events and the transaction of a request.
+///
+/// Only the values of a few common headers, and of the headers in `allowed`,
+/// are sent. The values of all other headers, which may carry credentials
+/// or personal data, are replaced with `[Filtered]`. Header names are
+/// matched case-insensitively.
+pub(crate) fn request_data<'a>(
+    method: &str,
+    url: &str,
+    query: Option<&str>,
+    headers: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    allowed: &[String],
+) -> Request {
+    let mut data = Map::new();
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        let value = if ALLOWED_HEADERS.contains(&name.as_str()) || allowed.contains(&name) {
+            String::from_utf8_lossy(value).into_owned()
+        } else {
+            FILTERED.to_string()
+        };
+        data.insert(name, value);
+    }
+
+    Request {
+        method: Some(method.to_string()),
+        url: url.parse().ok(),
+        query_string: query.map(str::to_string),
+        headers: data,
+        ..Default::default()
+    }
+}
+
+/// A request handled by the middleware, with its own hub and transaction.
+///
+/// If it is dropped before the response is ready, because the client went
+/// away, the transaction finishes with the `Cancelled` status.
+pub(crate) struct ServerRequest {
+    hub: Arc<Hub>,
+    name: String,
+    transaction: Option<Transaction>,
+}
+
+impl ServerRequest {
+    /// Start handling the request named `name` in a new hub, with `data`
+    /// attached to its transaction and to the events reported meanwhile.
+    ///
+    /// `call` runs in the new hub and calls the inner service.
+    pub(crate) fn start<F>(
+        name: String,
+        data: Request,
+        call: impl FnOnce() -> F,
+    ) -> (ServerRequest, F) {
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        let (transaction, future) = Hub::run(hub.clone(), || {
+            let transaction = start_transaction(&name, "http.server");
+            transaction.set_request(data.clone());
+            sentry::configure_scope(|scope| {
+                scope.set_transaction(Some(&name));
+                scope.add_event_processor(move |mut event| {
+                    if event.request.is_none() {
+                        event.request = Some(data.clone());
+                    }
+                    Some(event)
+                });
+            });
+            (transaction, call())
+        });
+
+        let request = ServerRequest {
+            hub,
+            name,
+            transaction: Some(transaction),
+        };
+        (request, future)
+    }
+
+    /// The hub the request is handled in.
+    pub(crate) fn hub(&self) -> &Arc<Hub> {
+        &self.hub
+    }
+
+    /// Finish the request with an HTTP `status`, reporting a 5xx status with
+    /// the `error` that produced it or, without one, as a message.
+    ///
+    /// Returns the id of the reported event, to be sent back to the client in
+    /// the [`EVENT_ID_HEADER`] header.
+    pub(crate) fn respond(
+        &mut self,
+        status: u16,
+        reason: Option<&str>,
+        error: Option<&dyn Error>,
+    ) -> Option<Uuid> {
+        let transaction = self.transaction.take().expect("request already finished");
+        Hub::run(self.hub.clone(), || {
+            transaction.set_status(span_status(status));
+            transaction.set_data("http.response.status_code", status);
+            if status < 500 {
+                return None;
+            }
+            let id = match error {
+                Some(error) => report_error(error),
+                None => report_server_error(&self.name, status, reason),
+            };
+            Some(id).filter(|id| !id.is_nil())
+        })
+    }
+
+    /// Finish the request after the inner service failed without a response.
+    #[cfg(feature = "tower")]
+    pub(crate) fn fail(&mut self) {
+        let transaction = self.transaction.take().expect("request already finished");
+        transaction.set_status(SpanStatus::InternalError);
+        Hub::run(self.hub.clone(), || drop(transaction));
+    }
+
+    /// Report a panic of the handler, finish the request and resume the panic.
+    pub(crate) fn panicked(&mut self, payload: Box<dyn Any + Send>) -> ! {
+        let transaction = self.transaction.take().expect("request already finished");
+        let location = Hub::run(self.hub.clone(), || {
+            let location = panic::report_caught_panic(payload.as_ref());
+            transaction.set_status(SpanStatus::InternalError);
+            drop(transaction);
+            location
+        });
+        panic::resume_reported(payload, location)
+    }
+}
+
+impl Drop for ServerRequest {
+    fn drop(&mut self) {
+        // The client went away before the response was ready.
+        if let Some(transaction) = self.transaction.take() {
+            transaction.set_status(SpanStatus::Cancelled);
+            Hub::run(self.hub.clone(), || drop(transaction));
+        }
+    }
+}
+
+/// Map an HTTP status code to the status of the request's transaction.
+pub(crate) fn span_status(status: u16) -> SpanStatus {
+    match status {
+        400 => SpanStatus::InvalidArgument,
+        401 => SpanStatus::Unauthenticated,
+        403 => SpanStatus::PermissionDenied,
+        404 => SpanStatus::NotFound,
+        409 => SpanStatus::AlreadyExists,
+        429 => SpanStatus::ResourceExhausted,
+        499 => SpanStatus::Cancelled,
+        501 => SpanStatus::Unimplemented,
+        503 => SpanStatus::Unavailable,
+        504 => SpanStatus::DeadlineExceeded,
+        _ if (400..500).contains(&status) => SpanStatus::FailedPrecondition,
+        _ if status >= 500 => SpanStatus::InternalError,
+        _ => SpanStatus::Ok,
+    }
+}
+
+/// Report a response with a 5xx status for the request named `name`.
+fn report_server_error(name: &str, status: u16, reason: Option<&str>) -> Uuid {
+    let message = match reason {
+        Some(reason) => format!("{} responded with {} {}", name, status, reason),
+        None => format!("{} responded with {}", name, status),
+    };
+    sentry::capture_event(Event {
+        message: Some(message),
+        level: Level::Error,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_data() {
+        let headers: [(&str, &[u8]); 6] = [
+            ("Authorization", b"Bearer secret"),
+            ("user-agent", b"curl/8.0"),
+            ("x-upstream-token", b"abc"),
+            ("X-Forwarded-For", b"203.0.113.7"),
+            ("x-request-id", b"42"),
+            ("accept", b"*/*"),
+        ];
+        let request = request_data(
+            "GET",
+            "http://localhost/orders",
+            Some("page=2"),
+            headers,
+            &["x-request-id".to_string()],
+        );
+
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(request.url.unwrap().as_str(), "http://localhost/orders");
+        assert_eq!(request.query_string.as_deref(), Some("page=2"));
+        assert_eq!(request.headers["authorization"], FILTERED);
+        assert_eq!(request.headers["x-upstream-token"], FILTERED);
+        assert_eq!(request.headers["x-forwarded-for"], FILTERED);
+        assert_eq!(request.headers["x-request-id"], "42");
+        assert_eq!(request.headers["user-agent"], "curl/8.0");
+        assert_eq!(request.headers["accept"], "*/*");
+    }
+
+    #[test]
+    fn test_span_status() {
+        assert_eq!(span_status(204), SpanStatus::Ok);
+        assert_eq!(span_status(302), SpanStatus::Ok);
+        assert_eq!(span_status(404), SpanStatus::NotFound);
+        assert_eq!(span_status(422), SpanStatus::FailedPrecondition);
+        assert_eq!(span_status(503), SpanStatus::Unavailable);
+        assert_eq!(span_status(520), SpanStatus::InternalError);
+    }
+}
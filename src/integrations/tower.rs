@@ -0,0 +1,304 @@
+//! Middleware for HTTP servers built on `tower`, such as axum.
+//!
+//! ```no_run
+//! use axum::routing::get;
+//! use axum::Router;
+//! use drcode_rust::tower::DrCodeLayer;
+//!
+//! let app: Router = Router::new()
+//!     .route("/users/{id}", get(|| async { "user" }))
+//!     .layer(DrCodeLayer::new().allow_header("x-request-id"));
+//! ```
+
+use super::server::{request_data, ServerRequest, EVENT_ID_HEADER};
+use crate::future::CatchUnwind;
+use http::header::{HeaderValue, HOST};
+use http::{Request, Response};
+use sentry::Hub;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A `tower` layer that reports failed requests and times each request.
+///
+/// Each request runs with its own hub, with a copy of the scope the server
+/// was started with, so scope changes made while handling one request do not
+/// leak into others. The request is timed by an `http.server` transaction
+/// named after its method and route. With the `axum` feature, the route is
+/// the path pattern matched by the router, such as `/users/{id}`; otherwise
+/// it is the path of the request.
+///
+/// The method, URL, query string and headers of the request are attached to
+/// its transaction and to the events reported while handling it. Only the
+/// values of a few common headers, such as `Accept`, `Content-Type` and
+/// `User-Agent`, are sent; the values of all other headers, which may carry
+/// credentials or personal data, are replaced with `[Filtered]`.
+///
+/// Responses with a 5xx status are reported, and the id of the event is
+/// returned to the client in the `X-DrCode-Event-Id` header. Panics in the
+/// handler are reported and then resumed.
+#[derive(Clone, Default)]
+pub struct DrCodeLayer {
+    allowed_headers: Arc<Vec<String>>,
+}
+
+impl DrCodeLayer {
+    /// Create a layer that only sends the values of the default headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also send the value of the header `name`.
+    pub fn allow_header(mut self, name: &str) -> Self {
+        Arc::make_mut(&mut self.allowed_headers).push(name.to_ascii_lowercase());
+        self
+    }
+}
+
+impl<S> Layer<S> for DrCodeLayer {
+    type Service = DrCodeService<S>;
+
+    fn layer(&self, inner: S) -> DrCodeService<S> {
+        DrCodeService {
+            inner,
+            allowed_headers: self.allowed_headers.clone(),
+        }
+    }
+}
+
+/// Service returned by [`DrCodeLayer`].
+#[derive(Clone)]
+pub struct DrCodeService<S> {
+    inner: S,
+    allowed_headers: Arc<Vec<String>>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for DrCodeService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let name = format!("{} {}", request.method(), route(&request));
+        let data = request_data(
+            request.method().as_str(),
+            &request_url(&request),
+            request.uri().query(),
+            request
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+            &self.allowed_headers,
+        );
+
+        let (request, future) =
+            ServerRequest::start(name, data, || CatchUnwind::new(self.inner.call(request)));
+        ResponseFuture { request, future }
+    }
+}
+
+/// Future returned by [`DrCodeService`].
+#[must_use = "futures do nothing unless polled"]
+pub struct ResponseFuture<F> {
+    request: ServerRequest,
+    future: CatchUnwind<F>,
+}
+
+impl<F, ResBody, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = Result<Response<ResBody>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let output = match Hub::run(this.request.hub().clone(), || {
+            Pin::new(&mut this.future).poll(cx)
+        }) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match output {
+            Ok(Ok(mut response)) => {
+                let status = response.status();
+                let id = this
+                    .request
+                    .respond(status.as_u16(), status.canonical_reason(), None);
+                if let Some(id) = id {
+                    let value = HeaderValue::from_str(&id.to_string()).unwrap();
+                    response.headers_mut().insert(EVENT_ID_HEADER, value);
+                }
+                Poll::Ready(Ok(response))
+            }
+            Ok(Err(error)) => {
+                this.request.fail();
+                Poll::Ready(Err(error))
+            }
+            Err(payload) => this.request.panicked(payload),
+        }
+    }
+}
+
+fn route<B>(request: &Request<B>) -> &str {
+    #[cfg(feature = "axum")]
+    if let Some(path) = request.extensions().get::<axum::extract::MatchedPath>() {
+        return path.as_str();
+    }
+    request.uri().path()
+}
+
+fn request_url<B>(request: &Request<B>) -> String {
+    let uri = request.uri();
+    let host = uri
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| request.headers().get(HOST)?.to_str().ok())
+        .unwrap_or("localhost");
+    format!(
+        "{}://{}{}",
+        uri.scheme_str().unwrap_or("http"),
+        host,
+        uri.path()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{capture_items, events, transactions};
+    use sentry::protocol::{EnvelopeItem, Value};
+    use std::convert::Infallible;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    async fn handle(request: Request<()>) -> Result<Response<String>, Infallible> {
+        match request.uri().path() {
+            "/panic" => panic!("handler panicked"),
+            "/fail" => {
+                sentry::configure_scope(|scope| scope.set_tag("handler", "fail"));
+                Ok(Response::builder().status(502).body(String::new()).unwrap())
+            }
+            _ => Ok(Response::new("ok".to_string())),
+        }
+    }
+
+    fn send(uri: &str) -> Response<String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let service = ServiceBuilder::new()
+            .layer(DrCodeLayer::new().allow_header("X-Request-Id"))
+            .service_fn(handle);
+        let request = Request::get(uri)
+            .header("host", "shop.example")
+            .header("authorization", "Bearer secret")
+            .header("x-upstream-token", "abc")
+            .header("x-request-id", "42")
+            .header("user-agent", "curl/8.0")
+            .body(())
+            .unwrap();
+        runtime.block_on(service.oneshot(request)).unwrap()
+    }
+
+    #[test]
+    fn test_layer() {
+        let mut responses = Vec::new();
+        let items = capture_items(1.0, || {
+            responses.push(send("/orders?page=2"));
+            responses.push(send("/fail"));
+        });
+
+        assert!(!responses[0].headers().contains_key(EVENT_ID_HEADER));
+        let event_id = responses[1].headers()[EVENT_ID_HEADER].to_str().unwrap();
+
+        let events = events(&items);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id.to_string(), event_id);
+        assert_eq!(
+            events[0].message.as_deref(),
+            Some("GET /fail responded with 502 Bad Gateway")
+        );
+        assert_eq!(events[0].transaction.as_deref(), Some("GET /fail"));
+        assert_eq!(events[0].tags["handler"], "fail");
+        let request = events[0].request.as_ref().unwrap();
+        assert_eq!(
+            request.url.as_ref().unwrap().as_str(),
+            "http://shop.example/fail"
+        );
+        assert_eq!(request.headers["authorization"], "[Filtered]");
+        assert_eq!(request.headers["x-upstream-token"], "[Filtered]");
+        assert_eq!(request.headers["x-request-id"], "42");
+        assert_eq!(request.headers["user-agent"], "curl/8.0");
+
+        let transactions = transactions(&items);
+        assert_eq!(transactions.len(), 2);
+        let orders = &transactions[0];
+        assert_eq!(orders.name.as_deref(), Some("GET /orders"));
+        assert_eq!(
+            orders.request.as_ref().unwrap().query_string.as_deref(),
+            Some("page=2")
+        );
+        assert_eq!(orders.extra["http.response.status_code"], Value::from(200));
+        assert_eq!(transactions[1].name.as_deref(), Some("GET /fail"));
+        assert!(!transactions[0].tags.contains_key("handler"));
+    }
+
+    #[test]
+    fn test_layer_panic() {
+        let items = capture_items(1.0, || {
+            let result = std::panic::catch_unwind(|| send("/panic"));
+            assert!(result.is_err());
+        });
+
+        assert_eq!(items.len(), 2);
+        let (event, transaction) = match &items[..] {
+            [EnvelopeItem::Event(event), EnvelopeItem::Transaction(transaction)] => {
+                (event, transaction)
+            }
+            other => panic!("unexpected envelope items: {:?}", other),
+        };
+        assert_eq!(
+            event.exception[0].value.as_deref(),
+            Some("handler panicked")
+        );
+        assert_eq!(event.transaction.as_deref(), Some("GET /panic"));
+        assert!(event.request.is_some());
+        assert_eq!(transaction.name.as_deref(), Some("GET /panic"));
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_matched_path() {
+        use axum::body::Body;
+        use axum::routing::get;
+        use axum::Router;
+
+        let items = capture_items(1.0, || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            let app: Router = Router::new()
+                .route("/users/{id}", get(|| async { "user" }))
+                .layer(DrCodeLayer::new());
+            let request = Request::get("/users/42").body(Body::empty()).unwrap();
+            let response = runtime.block_on(app.oneshot(request)).unwrap();
+            assert!(response.status().is_success());
+        });
+
+        let names: Vec<_> = transactions(&items)
+            .iter()
+            .filter_map(|transaction| transaction.name.as_deref())
+            .collect();
+        assert_eq!(names, ["GET /users/{id}"]);
+    }
+}
@@ -0,0 +1,177 @@
+//! A `tracing` layer that sends events and spans to DrCode.
+//!
+//! ```no_run
+//! use tracing::Level;
+//! use tracing_subscriber::filter::Targets;
+//! use tracing_subscriber::prelude::*;
+//!
+//! let breadcrumbs = Targets::new()
+//!     .with_default(Level::INFO)
+//!     .with_target("hyper", Level::WARN);
+//!
+//! tracing_subscriber::registry()
+//!     .with(tracing_subscriber::fmt::layer())
+//!     .with(drcode_rust::tracing::layer().breadcrumbs(breadcrumbs))
+//!     .init();
+//! ```
+
+use sentry::integrations::tracing::{EventFilter, SentryLayer};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Create a layer with the default filters.
+///
+/// By default `error` events are reported as events, `warn` and `info`
+/// events are recorded as breadcrumbs, and spans from `info` up are recorded
+/// as performance spans of the current transaction, or as new transactions
+/// when there is none.
+pub fn layer<S>() -> Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut layer = Layer {
+        inner: SentryLayer::default(),
+        events: Targets::new().with_default(Level::ERROR),
+        breadcrumbs: Targets::new().with_default(Level::INFO),
+        spans: Targets::new().with_default(Level::INFO),
+    };
+    layer.rebuild();
+    layer
+}
+
+/// A `tracing_subscriber` layer sending `tracing` data to DrCode.
+///
+/// Each mapping is configured with a [`Targets`] filter, which sets a level
+/// per target and a default level for all other targets. An event is
+/// reported if the event filter enables it, and otherwise recorded as a
+/// breadcrumb if the breadcrumb filter enables it.
+pub struct Layer<S> {
+    inner: SentryLayer<S>,
+    events: Targets,
+    breadcrumbs: Targets,
+    spans: Targets,
+}
+
+impl<S> Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    /// Set which `tracing` events are reported as DrCode events.
+    pub fn events(mut self, filter: Targets) -> Self {
+        self.events = filter;
+        self.rebuild();
+        self
+    }
+
+    /// Set which `tracing` events are recorded as breadcrumbs.
+    pub fn breadcrumbs(mut self, filter: Targets) -> Self {
+        self.breadcrumbs = filter;
+        self.rebuild();
+        self
+    }
+
+    /// Set which `tracing` spans are recorded as performance spans.
+    pub fn spans(mut self, filter: Targets) -> Self {
+        self.spans = filter;
+        self.rebuild();
+        self
+    }
+
+    fn rebuild(&mut self) {
+        let events = self.events.clone();
+        let breadcrumbs = self.breadcrumbs.clone();
+        let spans = self.spans.clone();
+        self.inner = SentryLayer::default()
+            .event_filter(move |metadata| {
+                if enables(&events, metadata) {
+                    EventFilter::Event
+                } else if enables(&breadcrumbs, metadata) {
+                    EventFilter::Breadcrumb
+                } else {
+                    EventFilter::Ignore
+                }
+            })
+            .span_filter(move |metadata| enables(&spans, metadata));
+    }
+}
+
+fn enables(filter: &Targets, metadata: &Metadata<'_>) -> bool {
+    filter.would_enable(metadata.target(), metadata.level())
+}
+
+impl<S> tracing_subscriber::Layer<S> for Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        self.inner.on_event(event, ctx);
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, span: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(span, values, ctx);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{capture_items, events, transactions};
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_layer() {
+        let subscriber = tracing_subscriber::registry().with(
+            layer()
+                .breadcrumbs(
+                    Targets::new()
+                        .with_default(Level::INFO)
+                        .with_target("noisy", Level::WARN),
+                )
+                .events(
+                    Targets::new()
+                        .with_default(Level::ERROR)
+                        .with_target("payments", Level::WARN),
+                ),
+        );
+
+        let items = capture_items(1.0, || {
+            tracing::subscriber::with_default(subscriber, || {
+                let _span = tracing::info_span!("checkout").entered();
+                tracing::info!("cart loaded");
+                tracing::info!(target: "noisy", "ignored");
+                tracing::debug!("ignored");
+                tracing::warn!(target: "payments", "card declined");
+                tracing::error!("checkout failed");
+            });
+        });
+
+        let events = events(&items);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].breadcrumbs.len(), 1);
+        assert_eq!(
+            events[0].breadcrumbs[0].message.as_deref(),
+            Some("cart loaded")
+        );
+        assert_eq!(events[0].message.as_deref(), Some("card declined"));
+        assert_eq!(events[0].level, sentry::Level::Warning);
+        assert_eq!(events[1].message.as_deref(), Some("checkout failed"));
+
+        let transactions = transactions(&items);
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].name.as_deref(),
+            Some("drcode_rust::integrations::tracing::tests::checkout")
+        );
+    }
+}
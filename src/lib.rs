@@ -1,63 +1,209 @@
-
-use sentry::ClientInitGuard;
-use std::panic;
+use sentry::{ClientInitGuard, Hub, SentryFutureExt};
 use tokio::task;
 
-/// Configuration for the Nadeem Rust error reporting.
-pub struct Config {
-    pub public_key: String,
-    pub project_id: String,
+mod blocking;
+mod breadcrumb;
+mod chain;
+mod client;
+mod config;
+mod error;
+mod ext;
+mod future;
+#[cfg(feature = "macros")]
+mod macros;
+mod integrations;
+mod message;
+mod panic;
+mod performance;
+mod scope;
+#[cfg(test)]
+mod test_util;
+
+pub use blocking::{run_sync_with_error_reporting, spawn_blocking_reported, spawn_thread_reported};
+pub use breadcrumb::{
+    add_breadcrumb, add_http_breadcrumb, add_navigation_breadcrumb, add_query_breadcrumb, Breadcrumb,
+};
+pub use chain::DEFAULT_MAX_ERROR_CHAIN_DEPTH;
+pub use client::DrCode;
+pub use config::{Config, Scheme, CONFIG_FILE_ENV, DEFAULT_HOST};
+pub use error::{DrCodeError, TaskError};
+pub use ext::{ReportExt, ReportNoneExt};
+pub use future::{FutureExt, ReportErrors};
+#[cfg(feature = "actix")]
+pub use integrations::actix;
+#[cfg(feature = "anyhow")]
+pub use integrations::anyhow::report_anyhow;
+#[cfg(feature = "eyre")]
+pub use integrations::eyre::report_eyre;
+#[cfg(feature = "log")]
+pub use integrations::log;
+#[cfg(feature = "tower")]
+pub use integrations::tower;
+#[cfg(feature = "tracing")]
+pub use integrations::tracing;
+#[doc(hidden)]
+pub use message::capture_template as __capture_template;
+pub use message::capture_message;
+pub use panic::{setup_panic_hook, PanicHook, PanicHookGuard};
+pub use performance::{
+    start_span, start_transaction, Instrumented, Span, SpanStatus, Transaction,
+};
+pub use scope::{configure_scope, with_scope, Context, IpAddress, Scope, User};
+pub use sentry::types::Uuid;
+pub use sentry::Level;
+
+/// Wrap a function in a span, recording its arguments as span data.
+///
+/// The span is a child of the current span, or a new transaction when there
+/// is none. Both sync and async functions are supported. When the function
+/// returns `Result`, an `Err` marks the span as errored and is reported.
+///
+/// * `op = "db.query"` sets the operation of the span. Defaults to `"function"`.
+/// * `name = "..."` sets the description of the span. Defaults to the function name.
+/// * `skip(password, ...)` leaves arguments out of the span data. All other
+///   arguments are recorded with their `Debug` output.
+/// * `crate = "drcode"` sets the path of this crate if it was renamed.
+///
+/// ```no_run
+/// #[drcode_rust::instrument(op = "db.query", skip(password))]
+/// async fn login(user: &str, password: &str) -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use drcode_rust_macros::instrument;
+
+/// Initialize DrCode around `main`.
+///
+/// The configuration is loaded with [`Config::from_env`] and passed to
+/// [`try_init`], which also installs the panic hook. If the public key or
+/// project id is missing, a warning is printed and `main` runs without
+/// reporting. If the configuration is invalid, the error is printed and the
+/// process exits with code 1 before `main` runs. An `Err` returned from `main` is reported as a fatal event,
+/// and pending events are flushed before `main` returns.
+///
+/// `#[drcode_rust::main(tokio)]` runs an async `main` on a multi-threaded
+/// Tokio runtime. `crate = "drcode"` sets the path of this crate if it was
+/// renamed.
+///
+/// ```no_run
+/// #[drcode_rust::main(tokio)]
+/// async fn main() -> std::io::Result<()> {
+///     tokio::fs::read_to_string("orders.json").await?;
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use drcode_rust_macros::main;
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::macros::{
+        init_main, instrument, start_async_span, Output, ReportDebug, ReportDynError,
+        ReportStdError,
+    };
+    pub use tokio;
 }
 
 /// Initialize the Sentry client with the provided configuration and set up automatic error reporting.
 ///
 /// # Arguments
 ///
-/// * `config` - The configuration containing the public key and project ID.
+/// * `config` - The configuration containing the public key, project ID and endpoint.
 ///
 /// # Returns
 ///
-/// A `ClientInitGuard` which, when dropped, will flush all events.
-pub fn init(config: Config) -> ClientInitGuard {
-    let dsn = format!(
-        "https://{}@pulse.drcode.ai:443/{}",
-        config.public_key, config.project_id
-    );
+/// An [`InitGuard`] which, when dropped, will uninstall the panic hook and
+/// flush all events.
+///
+/// # Panics
+///
+/// Panics if the configuration is invalid. Use [`try_init`] to handle that case.
+pub fn init(config: Config) -> InitGuard {
+    try_init(config).unwrap_or_else(|e| panic!("Failed to initialize DrCode: {}", e))
+}
 
-    let guard = sentry::init((
-        dsn,
-        sentry::ClientOptions {
-            release: sentry::release_name!(),
-            attach_stacktrace: true,
-            ..Default::default()
-        },
-    ));
+/// Like [`init`], but returns an error instead of panicking on an invalid configuration.
+///
+/// The configuration is validated before `sentry::init` is called, so nothing
+/// is initialized when an error is returned.
+pub fn try_init(config: Config) -> Result<InitGuard, DrCodeError> {
+    let client = init_client(&config)?;
+    let panic_hook = PanicHook::new().install();
+
+    Ok(InitGuard {
+        _panic_hook: panic_hook,
+        client,
+    })
+}
+
+/// Keeps DrCode initialized, as returned by [`init`] and [`try_init`].
+///
+/// When dropped, it uninstalls the panic hook, restoring the hook that was
+/// installed before, and flushes all pending events. It dereferences to the
+/// `ClientInitGuard` of the client.
+#[must_use = "DrCode is shut down when the guard is dropped"]
+pub struct InitGuard {
+    _panic_hook: PanicHookGuard,
+    client: ClientInitGuard,
+}
 
-    // Set up custom panic hook for automatic reporting
-    let default_panic = panic::take_hook();
-    panic::set_hook(Box::new(move |panic_info| {
-        let payload = panic_info.payload().downcast_ref::<String>();
-        let message = payload.map(|s| s.as_str()).unwrap_or("Unknown panic");
-        
-        sentry::capture_message(message, sentry::Level::Fatal);
+impl std::ops::Deref for InitGuard {
+    type Target = ClientInitGuard;
 
-        default_panic(panic_info);
-    }));
+    fn deref(&self) -> &ClientInitGuard {
+        &self.client
+    }
+}
+
+/// Validate the configuration and bind a new client to the process-wide hub.
+pub(crate) fn init_client(config: &Config) -> Result<ClientInitGuard, DrCodeError> {
+    let options = config.client_options()?;
+    chain::set_max_depth(
+        config
+            .max_error_chain_depth
+            .unwrap_or(DEFAULT_MAX_ERROR_CHAIN_DEPTH),
+    );
+    Ok(sentry::init(options))
+}
 
-    guard
+/// Send all pending events and exit the process with the given exit code.
+///
+/// `std::process::exit` does not run destructors, so an [`InitGuard`] or
+/// [`DrCode`] client alive at that point would never flush. This waits up
+/// to the configured `shutdown_timeout` for pending events to be sent first.
+pub fn exit(code: i32) -> ! {
+    if let Some(client) = sentry::Hub::current().client() {
+        client.flush(Some(client.options().shutdown_timeout));
+    }
+    std::process::exit(code)
 }
 
 /// Report an error to Sentry manually.
 ///
+/// The error and every error in its `source()` chain are sent as chained
+/// exceptions, up to `Config::max_error_chain_depth` of them.
+///
 /// # Arguments
 ///
-/// * `error` - The error to report.
-pub fn report_error<E: std::error::Error + Send + Sync + 'static>(error: E) {
-    sentry::capture_error(&error);
+/// * `error` - The error to report. Borrowed errors, errors that are not
+///   `'static` and `dyn Error` trait objects are all accepted.
+///
+/// # Returns
+///
+/// The id of the reported event, which can be shown to users as an incident reference.
+pub fn report_error<E: std::error::Error + ?Sized>(error: &E) -> Uuid {
+    sentry::capture_event(chain::event_from_error(error))
 }
 
 /// Run an asynchronous task with automatic error reporting.
 ///
+/// The task runs with its own hub, forked from the current one. It starts
+/// with a copy of the caller's scope, so tags and breadcrumbs recorded before
+/// the call are attached to the reported error, while changes made to the
+/// scope inside the task stay local to it.
+///
 /// # Arguments
 ///
 /// * `future` - The future to run.
@@ -65,24 +211,70 @@ pub fn report_error<E: std::error::Error + Send + Sync + 'static>(error: E) {
 /// # Returns
 ///
 /// The result of the future.
+///
+/// # Panics
+///
+/// Resumes the panic if the task panics, and panics if the task is
+/// cancelled. Use [`try_run_with_error_reporting`] to handle those cases.
 pub async fn run_with_error_reporting<F, T, E>(future: F) -> Result<T, E>
 where
     F: std::future::Future<Output = Result<T, E>> + Send + 'static,
     T: Send + 'static,
     E: std::error::Error + Send + Sync + 'static,
 {
-    task::spawn(async move {
-        match future.await {
-            Ok(result) => Ok(result),
-            Err(e) => {
-                sentry::capture_error(&e);
-                Err(e)
+    match try_run_with_error_reporting(future).await {
+        Ok(result) => Ok(result),
+        Err(TaskError::Error(e)) => Err(e),
+        // Both have been reported already, and resuming does not run the panic hook again.
+        Err(e) => {
+            let location = match &e {
+                TaskError::Panic { location, .. } => location.clone(),
+                _ => None,
+            };
+            panic::resume_reported(Box::new(e.to_string()), location)
+        }
+    }
+}
+
+/// Like [`run_with_error_reporting`], but returns a [`TaskError`] instead of
+/// panicking when the task panics or is cancelled.
+///
+/// Panics are reported with their original message and, when the DrCode
+/// panic hook is installed, their location.
+pub async fn try_run_with_error_reporting<F, T, E>(future: F) -> Result<T, TaskError<E>>
+where
+    F: std::future::Future<Output = Result<T, E>> + Send + 'static,
+    T: Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let task = async move {
+        match future::CatchUnwind::new(future).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => {
+                report_error(&e);
+                Err(TaskError::Error(e))
             }
+            Err(payload) => Err(task_panic(payload)),
         }
-    }).await.unwrap_or_else(|e| {
-        sentry::capture_error(&e);
-        panic!("Task panicked: {:?}", e);
-    })
+    };
+
+    match task::spawn(task.bind_hub(Hub::new_from_top(Hub::current()))).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => Err(task_panic(e.into_panic())),
+        Err(_) => {
+            let error = TaskError::Cancelled;
+            report_error(&error);
+            Err(error)
+        }
+    }
+}
+
+/// Report the panic of a task and describe it as a [`TaskError`].
+fn task_panic<E>(payload: Box<dyn std::any::Any + Send>) -> TaskError<E> {
+    TaskError::Panic {
+        location: panic::report_caught_panic(payload.as_ref()),
+        message: panic::message_from_payload(payload.as_ref()).to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -94,8 +286,20 @@ mod tests {
         let config = Config {
             public_key: "test_key".to_string(),
             project_id: "test_project".to_string(),
+            ..Default::default()
         };
-        let _guard = init(config);
+        let guard = init(config);
+        assert!(guard.is_enabled());
+        assert!(panic::is_installed());
+    }
+
+    #[test]
+    fn test_try_init_rejects_invalid_config() {
+        let config = Config {
+            host: Some("https://pulse.drcode.ai".to_string()),
+            ..Config::new("test_key", "test_project")
+        };
+        assert!(matches!(try_init(config), Err(DrCodeError::InvalidConfig(_))));
     }
 
     #[tokio::test]
@@ -103,6 +307,7 @@ mod tests {
         let config = Config {
             public_key: "test_key".to_string(),
             project_id: "test_project".to_string(),
+            ..Default::default()
         };
         let _guard = init(config);
 
@@ -112,8 +317,145 @@ mod tests {
         assert!(result.is_ok());
 
         let error_result = run_with_error_reporting(async {
-            Err::<(), _>(std::io::Error::new(std::io::ErrorKind::Other, "Test error"))
+            Err::<(), _>(std::io::Error::other("Test error"))
         }).await;
         assert!(error_result.is_err());
     }
+
+    #[test]
+    fn test_run_with_error_reporting_breadcrumbs() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            add_breadcrumb(Breadcrumb {
+                message: Some("before task".to_string()),
+                ..Default::default()
+            });
+            let result = runtime.block_on(run_with_error_reporting(async {
+                add_query_breadcrumb("SELECT 1");
+                Err::<(), _>(std::io::Error::other("Test error"))
+            }));
+            assert!(result.is_err());
+        });
+
+        assert_eq!(events.len(), 1);
+        let breadcrumbs = &events[0].breadcrumbs.values;
+        assert_eq!(breadcrumbs.len(), 2);
+        assert_eq!(breadcrumbs[0].message.as_deref(), Some("before task"));
+        assert_eq!(breadcrumbs[1].message.as_deref(), Some("SELECT 1"));
+    }
+
+    #[test]
+    fn test_run_with_error_reporting_isolates_scopes() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            configure_scope(|scope| scope.set_tag("service", "checkout"));
+            let request = |request: u32| {
+                run_with_error_reporting(async move {
+                    configure_scope(|scope| scope.set_tag("request", request));
+                    task::yield_now().await;
+                    Err::<(), _>(std::io::Error::other("Test error"))
+                })
+            };
+            let (first, second) = runtime.block_on(async { tokio::join!(request(0), request(1)) });
+            assert!(first.is_err() && second.is_err());
+            report_error(&std::io::Error::other("outside"));
+        });
+
+        assert_eq!(events.len(), 3);
+        let mut requests: Vec<_> = events[..2].iter().map(|e| e.tags["request"].as_str()).collect();
+        requests.sort();
+        assert_eq!(requests, ["0", "1"]);
+        assert!(events.iter().all(|event| event.tags["service"] == "checkout"));
+        assert!(!events[2].tags.contains_key("request"));
+    }
+
+    #[test]
+    fn test_try_run_with_error_reporting() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            let error = runtime.block_on(try_run_with_error_reporting(async {
+                Err::<(), _>(std::io::Error::other("Test error"))
+            }));
+            assert!(matches!(error, Err(TaskError::Error(_))));
+
+            let panic = runtime.block_on(try_run_with_error_reporting(async {
+                panic!("task panic");
+                #[allow(unreachable_code)]
+                Ok::<(), std::io::Error>(())
+            }));
+            match panic {
+                Err(TaskError::Panic { message, .. }) => assert_eq!(message, "task panic"),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].exception[0].ty, "panic");
+        assert_eq!(events[1].exception[0].value.as_deref(), Some("task panic"));
+    }
+
+    #[test]
+    fn test_try_run_with_error_reporting_reported_panic() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            let panic = runtime.block_on(try_run_with_error_reporting(
+                async {
+                    panic!("reported in place");
+                    #[allow(unreachable_code)]
+                    Ok::<(), std::io::Error>(())
+                }
+                .report_errors(),
+            ));
+            assert!(matches!(panic, Err(TaskError::Panic { .. })));
+        });
+
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_try_run_with_error_reporting_location() {
+        let _hook = PanicHook::new().call_previous(false).install();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(try_run_with_error_reporting(async {
+            panic!("located panic");
+            #[allow(unreachable_code)]
+            Ok::<(), std::io::Error>(())
+        }));
+
+        match result {
+            Err(TaskError::Panic {
+                location: Some(location),
+                ..
+            }) => assert!(location.starts_with(file!())),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[derive(Debug)]
+    struct BorrowedError<'a>(&'a str);
+
+    impl std::fmt::Display for BorrowedError<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl std::error::Error for BorrowedError<'_> {}
+
+    #[test]
+    fn test_report_error() {
+        let message = String::from("borrowed error");
+        let mut ids = Vec::new();
+        let events = sentry::test::with_captured_events(|| {
+            ids.push(report_error(&BorrowedError(&message)));
+            let boxed: Box<dyn std::error::Error> =
+                Box::new(std::io::Error::other("boxed error"));
+            ids.push(report_error(boxed.as_ref()));
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_id, ids[0]);
+        assert_eq!(events[1].event_id, ids[1]);
+        assert_eq!(events[1].exception[0].value.as_deref(), Some("boxed error"));
+    }
 }
@@ -0,0 +1,310 @@
+//! Support code for the `instrument` and `main` attribute macros.
+//!
+//! The macros report the error of a function returning `Result` through the
+//! first of these traits that applies, by calling the method on
+//! `&&&Output(&result)`: method resolution tries the impls for `&&Output`
+//! before those for `&Output` and `Output`.
+
+use crate::{
+    performance, report_error, Config, DrCodeError, InitGuard, Instrumented, Level, Span,
+};
+use std::fmt::Debug;
+use std::future::Future;
+
+/// Initialize DrCode from the environment, exiting the process if the configuration is invalid.
+///
+/// Without a public key or project id, a warning is printed and `main` runs
+/// without reporting, so that binaries still start without credentials.
+pub fn init_main() -> Option<InitGuard> {
+    init_main_from(|name| std::env::var(name).ok())
+}
+
+/// Initialize DrCode from the variables returned by `lookup`, as [`init_main`] does.
+fn init_main_from(lookup: impl Fn(&str) -> Option<String>) -> Option<InitGuard> {
+    match Config::from_lookup(lookup).and_then(crate::try_init) {
+        Ok(guard) => Some(guard),
+        Err(DrCodeError::MissingField(field)) => {
+            eprintln!(
+                "DrCode is not initialized and errors are not reported: missing required field `{}`",
+                field
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!("Failed to initialize DrCode: {}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Start the span of an async function, which is only current while its
+/// future is polled by [`instrument`].
+pub fn start_async_span(op: &str, name: &str) -> Span {
+    performance::start_detached_span(op, name)
+}
+
+/// Make `span` current each time the body of an async function is polled.
+pub fn instrument<F: Future>(span: &Span, future: F) -> Instrumented<&Span, F> {
+    Instrumented::new(span.span(), span, future)
+}
+
+pub struct Output<'a, T>(pub &'a T);
+
+/// Reports errors that do not implement `std::error::Error` themselves but
+/// wrap one, such as `anyhow::Error` or `Box<dyn Error>`.
+pub trait ReportDynError {
+    fn report_output(&self) -> bool;
+}
+
+impl<T> ReportDynError for &&Output<'_, Result<T, Box<dyn std::error::Error>>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            report_error(&**e);
+        }
+        self.0.is_err()
+    }
+}
+
+impl<T> ReportDynError for &&Output<'_, Result<T, Box<dyn std::error::Error + Send + Sync>>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            report_error(&**e);
+        }
+        self.0.is_err()
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl<T> ReportDynError for &&Output<'_, Result<T, anyhow::Error>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            crate::report_anyhow(e);
+        }
+        self.0.is_err()
+    }
+}
+
+#[cfg(feature = "eyre")]
+impl<T> ReportDynError for &&Output<'_, Result<T, eyre::Report>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            crate::report_eyre(e);
+        }
+        self.0.is_err()
+    }
+}
+
+/// Reports errors implementing `std::error::Error` with their source chain.
+pub trait ReportStdError {
+    fn report_output(&self) -> bool;
+}
+
+impl<T, E: std::error::Error> ReportStdError for &Output<'_, Result<T, E>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            report_error(e);
+        }
+        self.0.is_err()
+    }
+}
+
+/// Reports any other error as a message built from its `Debug` output.
+pub trait ReportDebug {
+    fn report_output(&self) -> bool;
+}
+
+impl<T, E: Debug> ReportDebug for Output<'_, Result<T, E>> {
+    fn report_output(&self) -> bool {
+        if let Err(e) = self.0 {
+            crate::capture_message(&format!("{:?}", e), Level::Error);
+        }
+        self.0.is_err()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{instrument, start_transaction, test_util, Level, SpanStatus};
+    use sentry::protocol::{Transaction, Value};
+    use std::io;
+
+    #[instrument(crate = "crate", op = "db.query", skip(password))]
+    fn login(user: &str, password: &str) -> io::Result<usize> {
+        if password.is_empty() {
+            return Err(io::Error::other("empty password"));
+        }
+        Ok(user.len())
+    }
+
+    #[instrument(crate = "crate", name = "load orders")]
+    async fn load(count: u32) -> Result<u32, String> {
+        tokio::task::yield_now().await;
+        let count = count.checked_sub(1).ok_or("no orders")?;
+        Ok(count)
+    }
+
+    #[instrument(crate = "crate")]
+    fn total(prices: &[u32]) -> u32 {
+        prices.iter().sum()
+    }
+
+    #[instrument(crate = "crate")]
+    async fn step(n: u32) -> u32 {
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+        n
+    }
+
+    fn capture(f: impl FnOnce()) -> (Vec<sentry::protocol::Event<'static>>, Transaction<'static>) {
+        let items = test_util::capture_items(1.0, f);
+        let transactions = test_util::transactions(&items);
+        assert_eq!(transactions.len(), 1);
+        let events = test_util::events(&items).into_iter().cloned().collect();
+        (events, transactions[0].clone())
+    }
+
+    #[test]
+    fn test_instrument() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (events, transaction) = capture(|| {
+            let _transaction = start_transaction("checkout", "task");
+            assert_eq!(login("jane", "secret").unwrap(), 4);
+            assert!(login("jane", "").is_err());
+            assert_eq!(total(&[1, 2]), 3);
+            runtime.block_on(async {
+                assert_eq!(load(2).await, Ok(1));
+                assert!(load(0).await.is_err());
+            });
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].exception[0].value.as_deref(), Some("empty password"));
+        assert_eq!(events[1].message.as_deref(), Some("\"no orders\""));
+
+        let spans = &transaction.spans;
+        assert_eq!(spans.len(), 5);
+        assert_eq!(spans[0].op.as_deref(), Some("db.query"));
+        assert_eq!(spans[0].description.as_deref(), Some("login"));
+        assert_eq!(spans[0].data["user"], Value::from("\"jane\""));
+        assert!(!spans[0].data.contains_key("password"));
+        assert_eq!(spans[0].status, Some(SpanStatus::Ok));
+        assert_eq!(spans[1].status, Some(SpanStatus::InternalError));
+        assert_eq!(spans[2].data["prices"], Value::from("[1, 2]"));
+        assert_eq!(spans[3].op.as_deref(), Some("function"));
+        assert_eq!(spans[3].description.as_deref(), Some("load orders"));
+        assert_eq!(spans[4].status, Some(SpanStatus::InternalError));
+    }
+
+    #[test]
+    fn test_instrument_concurrent() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (_, transaction) = capture(|| {
+            let transaction = start_transaction("batch", "task");
+            runtime.block_on(async {
+                assert_eq!(tokio::join!(step(1), step(2)), (1, 2));
+            });
+            transaction.finish();
+        });
+
+        let root = match transaction.contexts.get("trace") {
+            Some(sentry::protocol::Context::Trace(trace)) => trace.span_id,
+            other => panic!("unexpected trace context: {:?}", other),
+        };
+        assert_eq!(transaction.spans.len(), 2);
+        for span in &transaction.spans {
+            assert_eq!(span.parent_span_id, Some(root));
+        }
+    }
+
+    #[test]
+    fn test_instrument_boxed_error() {
+        #[instrument(crate = "crate")]
+        fn open(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path)).into())
+        }
+
+        #[instrument(crate = "crate")]
+        fn parse(input: &str) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(input.parse()?)
+        }
+
+        let events = sentry::test::with_captured_events(|| {
+            assert!(open("orders.csv").is_err());
+            assert!(parse("x").is_err());
+        });
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].exception[0].value.as_deref(), Some("orders.csv not found"));
+        assert!(events[0].message.is_none());
+        assert_eq!(
+            events[1].exception[0].value.as_deref(),
+            Some("invalid digit found in string")
+        );
+    }
+
+    /// The crate as seen by `main`, initialized from fixed variables instead
+    /// of the process environment.
+    mod krate {
+        pub use crate::*;
+
+        pub mod __private {
+            pub use crate::__private::*;
+
+            /// Without any `DRCODE_*` variable, `main` runs without
+            /// initializing a client, so its events go to the test hub.
+            pub fn init_main() -> Option<crate::InitGuard> {
+                super::super::super::init_main_from(|_| None)
+            }
+        }
+    }
+
+    #[test]
+    fn test_main() {
+        #[crate::main(crate = "krate")]
+        fn sync_main() -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        #[crate::main(tokio, crate = "krate")]
+        async fn async_main() -> io::Result<u32> {
+            tokio::task::yield_now().await;
+            Ok(7)
+        }
+
+        let events = sentry::test::with_captured_events(|| {
+            assert!(sync_main().is_err());
+            assert_eq!(async_main().unwrap(), 7);
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Level::Fatal);
+        assert_eq!(events[0].exception[0].value.as_deref(), Some("failed"));
+    }
+
+    #[test]
+    fn test_init_main_from() {
+        let guard = super::init_main_from(|name| match name {
+            "DRCODE_PUBLIC_KEY" => Some("test_key".to_string()),
+            "DRCODE_PROJECT_ID" => Some("test_project".to_string()),
+            "DRCODE_SAMPLE_RATE" => Some("0.0".to_string()),
+            _ => None,
+        });
+        assert!(guard.is_some_and(|guard| guard.is_enabled()));
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_instrument_anyhow() {
+        #[instrument(crate = "crate")]
+        fn parse(input: &str) -> anyhow::Result<u32> {
+            Ok(input.parse()?)
+        }
+
+        let events = sentry::test::with_captured_events(|| {
+            assert!(parse("x").is_err());
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].exception[0].value.as_deref(),
+            Some("invalid digit found in string")
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{Level, Uuid};
+use sentry::protocol::{Event, LogEntry, Value};
+
+/// Capture a message with the given severity level.
+///
+/// Use the [`capture_message!`](crate::capture_message!) macro for messages
+/// built from a format string, so that messages which only differ in their
+/// arguments are grouped into one issue.
+pub fn capture_message(message: &str, level: Level) -> Uuid {
+    sentry::capture_message(message, level)
+}
+
+/// Capture a message built from a format string.
+///
+/// The unformatted template is sent as the grouping key and the formatted
+/// arguments as its parameters, so `"user {} failed login"` is reported as a
+/// single issue whatever the user id. The level defaults to `Level::Info`
+/// and can be given as the first argument. Returns the id of the event.
+///
+/// ```no_run
+/// use drcode_rust::{capture_message, Level};
+///
+/// let id = 42;
+/// capture_message!("user {} failed login", id);
+/// capture_message!(Level::Warning, "retrying job {id} after {:?}", std::time::Duration::from_secs(5));
+/// ```
+#[macro_export]
+macro_rules! capture_message {
+    ($template:literal $($args:tt)*) => {
+        $crate::capture_message!($crate::Level::Info, $template $($args)*)
+    };
+    ($level:expr, $template:literal $($args:tt)*) => {
+        $crate::__capture_template(
+            $level,
+            $template,
+            ::std::format!($template $($args)*),
+        )
+    };
+}
+
+#[doc(hidden)]
+pub fn capture_template(level: Level, template: &'static str, formatted: String) -> Uuid {
+    sentry::capture_event(event_from_template(level, template, formatted))
+}
+
+pub(crate) fn event_from_template(level: Level, template: &str, formatted: String) -> Event<'static> {
+    let (message, literals) = parse_template(template);
+    let params = extract_params(&literals, &formatted)
+        .into_iter()
+        .map(Value::from)
+        .collect();
+
+    Event {
+        message: Some(formatted),
+        logentry: Some(LogEntry { message, params }),
+        level,
+        ..Default::default()
+    }
+}
+
+/// Split a format string into its literal pieces around each placeholder.
+///
+/// Also returns the template with every placeholder replaced by `%s`, the
+/// form DrCode expects for the parameters of a log entry.
+fn parse_template(template: &str) -> (String, Vec<String>) {
+    let mut message = String::with_capacity(template.len());
+    let mut literals = vec![String::new()];
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                message.push('{');
+                literals.last_mut().unwrap().push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                message.push('}');
+                literals.last_mut().unwrap().push('}');
+            }
+            '{' => {
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+                message.push_str("%s");
+                literals.push(String::new());
+            }
+            '%' => {
+                message.push_str("%%");
+                literals.last_mut().unwrap().push('%');
+            }
+            c => {
+                message.push(c);
+                literals.last_mut().unwrap().push(c);
+            }
+        }
+    }
+
+    (message, literals)
+}
+
+/// Recover the formatted arguments by matching the literal pieces of the
+/// template against the formatted message.
+///
+/// Returns no parameters if the message does not match the template.
+fn extract_params(literals: &[String], formatted: &str) -> Vec<String> {
+    let (first, rest) = literals.split_first().expect("at least one literal");
+    let Some(mut remaining) = formatted.strip_prefix(first.as_str()) else {
+        return Vec::new();
+    };
+
+    let mut params = Vec::with_capacity(rest.len());
+    for (i, literal) in rest.iter().enumerate() {
+        let end = if i == rest.len() - 1 {
+            match remaining.strip_suffix(literal.as_str()) {
+                Some(param) => param.len(),
+                None => return Vec::new(),
+            }
+        } else if literal.is_empty() {
+            remaining.len()
+        } else {
+            match remaining.find(literal.as_str()) {
+                Some(end) => end,
+                None => return Vec::new(),
+            }
+        };
+        params.push(remaining[..end].to_string());
+        remaining = &remaining[end + literal.len()..];
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_message_macro() {
+        let id = 42;
+        let events = sentry::test::with_captured_events(|| {
+            crate::capture_message("plain message", Level::Error);
+            capture_message!("user {} failed login", id);
+            capture_message!(Level::Warning, "user {id} failed login after {:?}", [1, 2]);
+        });
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].message.as_deref(), Some("plain message"));
+        assert_eq!(events[0].level, Level::Error);
+
+        assert_eq!(events[1].message.as_deref(), Some("user 42 failed login"));
+        assert_eq!(events[1].level, Level::Info);
+        let logentry = events[1].logentry.as_ref().unwrap();
+        assert_eq!(logentry.message, "user %s failed login");
+        assert_eq!(logentry.params, vec![Value::from("42")]);
+
+        assert_eq!(events[2].level, Level::Warning);
+        let logentry = events[2].logentry.as_ref().unwrap();
+        assert_eq!(logentry.message, "user %s failed login after %s");
+        assert_eq!(
+            logentry.params,
+            vec![Value::from("42"), Value::from("[1, 2]")]
+        );
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let (message, literals) = parse_template("{{literal}} 100% {:>5}!");
+        assert_eq!(message, "{literal} 100%% %s!");
+        assert_eq!(literals, vec!["{literal} 100% ", "!"]);
+
+        let (_, literals) = parse_template("{}{}");
+        assert_eq!(extract_params(&literals, "ab"), vec!["ab", ""]);
+
+        let (_, literals) = parse_template("user {} failed login");
+        assert!(extract_params(&literals, "unrelated message").is_empty());
+    }
+}
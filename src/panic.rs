@@ -0,0 +1,387 @@
+use sentry::integrations::backtrace::current_stacktrace;
+use sentry::protocol::{Context, Event, Exception, Map, Mechanism, Value};
+use sentry::{Hub, Level};
+use std::panic::{self, AssertUnwindSafe, Location, PanicHookInfo};
+use std::any::Any;
+use std::cell::Cell;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+type Hook = dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static;
+
+struct HookState {
+    installs: usize,
+    previous: Option<Arc<Hook>>,
+    call_previous: bool,
+    flush_timeout: Option<Duration>,
+}
+
+static HOOK_STATE: Mutex<HookState> = Mutex::new(HookState {
+    installs: 0,
+    previous: None,
+    call_previous: true,
+    flush_timeout: None,
+});
+
+thread_local! {
+    /// The location of the last panic on this thread seen by the DrCode hook.
+    static LAST_LOCATION: Cell<Option<String>> = const { Cell::new(None) };
+
+    /// The address and location of the last reported panic resumed on this thread.
+    static RESUMED: Cell<Option<(usize, Option<String>)>> = const { Cell::new(None) };
+}
+
+/// Serializes installing and uninstalling the hook.
+///
+/// Unlike `HOOK_STATE`, it is never locked by the hook itself, so it can be
+/// held while calling `take_hook` and `set_hook`, which run the hook if they
+/// panic.
+static INSTALL: Mutex<()> = Mutex::new(());
+
+fn hook_state() -> MutexGuard<'static, HookState> {
+    HOOK_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn install_lock() -> MutexGuard<'static, ()> {
+    INSTALL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Options for installing the DrCode panic hook.
+///
+/// The hook is installed at most once per process. Installing it again while
+/// it is installed only keeps it installed longer, and the options of the
+/// first installation stay in effect.
+#[derive(Debug, Clone)]
+pub struct PanicHook {
+    call_previous: bool,
+    flush_timeout: Option<Duration>,
+}
+
+impl Default for PanicHook {
+    fn default() -> Self {
+        PanicHook {
+            call_previous: true,
+            flush_timeout: None,
+        }
+    }
+}
+
+impl PanicHook {
+    /// Create the default panic hook options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to run the previously installed hook after reporting a panic.
+    ///
+    /// Defaults to `true`, which keeps the usual panic message on stderr.
+    pub fn call_previous(mut self, call_previous: bool) -> Self {
+        self.call_previous = call_previous;
+        self
+    }
+
+    /// Make the hook wait up to `timeout` for the panic event to be sent.
+    ///
+    /// When built with `panic = "abort"`, the hook always blocks the
+    /// panicking thread until the event is sent, by default for up to the
+    /// `shutdown_timeout` of the client, so the event is not lost when the
+    /// process aborts. Otherwise it only waits when this is set, since most
+    /// panics unwind and many are caught, and the event is sent in the
+    /// background meanwhile.
+    pub fn flush_timeout(mut self, timeout: Duration) -> Self {
+        self.flush_timeout = Some(timeout);
+        self
+    }
+
+    /// Install the hook, returning a guard that uninstalls it when dropped.
+    ///
+    /// The hook cannot be installed from a thread that is panicking, in
+    /// which case the returned guard does nothing.
+    pub fn install(self) -> PanicHookGuard {
+        let _install = install_lock();
+        if hook_state().installs == 0 {
+            // `take_hook` and `set_hook` panic when called while unwinding.
+            if thread::panicking() {
+                return PanicHookGuard { installed: false };
+            }
+            let previous = Arc::from(panic::take_hook());
+            let mut state = hook_state();
+            state.previous = Some(previous);
+            state.call_previous = self.call_previous;
+            state.flush_timeout = self.flush_timeout;
+            drop(state);
+            panic::set_hook(Box::new(panic_hook));
+        }
+        hook_state().installs += 1;
+        PanicHookGuard { installed: true }
+    }
+}
+
+/// Keeps the DrCode panic hook installed.
+///
+/// When the last guard is dropped, the hook that was installed before the
+/// DrCode hook is restored. A guard dropped while its thread is panicking
+/// leaves the hook installed for the rest of the process, since the panic
+/// hook cannot be changed while unwinding.
+#[must_use = "the panic hook is uninstalled when the guard is dropped"]
+#[derive(Debug)]
+pub struct PanicHookGuard {
+    installed: bool,
+}
+
+impl Drop for PanicHookGuard {
+    fn drop(&mut self) {
+        if !self.installed || thread::panicking() {
+            return;
+        }
+        let _install = install_lock();
+        let previous = {
+            let mut state = hook_state();
+            state.installs -= 1;
+            if state.installs == 0 {
+                state.previous.take()
+            } else {
+                None
+            }
+        };
+        if let Some(previous) = previous {
+            panic::set_hook(Box::new(move |info| previous(info)));
+        }
+    }
+}
+
+/// Whether the DrCode panic hook is currently installed and reporting panics.
+pub(crate) fn is_installed() -> bool {
+    hook_state().installs > 0
+}
+
+/// Report a panic caught with `catch_unwind`, unless the DrCode hook or an
+/// inner catcher that resumed it with [`resume_reported`] already did.
+///
+/// Returns the location of the panic, which is only known when the hook is installed.
+pub(crate) fn report_caught_panic(payload: &(dyn Any + Send)) -> Option<String> {
+    if let Some((address, location)) = RESUMED.with(Cell::take) {
+        if address == payload_address(payload) {
+            return location;
+        }
+    }
+    if is_installed() {
+        LAST_LOCATION.with(Cell::take)
+    } else {
+        sentry::capture_event(event_from_panic_payload(payload));
+        None
+    }
+}
+
+/// Resume a panic that has been reported with [`report_caught_panic`].
+///
+/// The payload is remembered so that catchers further up the stack do not
+/// report it again. `location` is what `report_caught_panic` returned.
+pub(crate) fn resume_reported(payload: Box<dyn Any + Send>, location: Option<String>) -> ! {
+    let address = payload_address(payload.as_ref());
+    RESUMED.with(|resumed| resumed.set(Some((address, location))));
+    panic::resume_unwind(payload)
+}
+
+/// Run `f`, catching a panic in it like `std::panic::catch_unwind`.
+///
+/// A panic resumed with [`resume_reported`] before the call, and caught by
+/// someone else since, is forgotten first, so that only a panic resumed
+/// within `f` counts as already reported.
+pub(crate) fn catch_unwind<R>(f: impl FnOnce() -> R) -> thread::Result<R> {
+    RESUMED.with(|resumed| resumed.set(None));
+    panic::catch_unwind(AssertUnwindSafe(f))
+}
+
+fn payload_address(payload: &(dyn Any + Send)) -> usize {
+    payload as *const dyn Any as *const () as usize
+}
+
+/// Set up a panic hook that reports panics before running the previously installed hook.
+///
+/// The hook stays installed for the rest of the process. Calling this more
+/// than once has no further effect.
+pub fn setup_panic_hook() {
+    std::mem::forget(PanicHook::new().install());
+}
+
+fn panic_hook(info: &PanicHookInfo<'_>) {
+    let (previous, flush_timeout) = {
+        let state = hook_state();
+        let previous = if state.call_previous {
+            state.previous.clone()
+        } else {
+            None
+        };
+        (previous, state.flush_timeout)
+    };
+
+    LAST_LOCATION.with(|last| last.set(info.location().map(ToString::to_string)));
+
+    let hub = Hub::current();
+    hub.capture_event(event_from_panic_info(info));
+    if cfg!(panic = "abort") || flush_timeout.is_some() {
+        if let Some(client) = hub.client() {
+            client.flush(Some(
+                flush_timeout.unwrap_or(client.options().shutdown_timeout),
+            ));
+        }
+    }
+
+    if let Some(previous) = previous {
+        previous(info);
+    }
+}
+
+/// Extract the message of a panic payload, which is a `&'static str` for
+/// `panic!("literal")` and a `String` for formatted panics.
+pub(crate) fn message_from_payload(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Unknown panic"
+    }
+}
+
+/// Build a fatal exception event for a panic, with its location and thread as context.
+pub(crate) fn event_from_panic_info(info: &PanicHookInfo<'_>) -> Event<'static> {
+    event_from_panic(message_from_payload(info.payload()), info.location())
+}
+
+/// Build a fatal exception event for a panic payload caught with `catch_unwind`.
+///
+/// The location of the panic is not part of the payload, so only the thread
+/// is added as context.
+pub(crate) fn event_from_panic_payload(payload: &(dyn Any + Send)) -> Event<'static> {
+    event_from_panic(message_from_payload(payload), None)
+}
+
+fn event_from_panic(message: &str, location: Option<&Location<'_>>) -> Event<'static> {
+    let mut context = Map::new();
+    if let Some(location) = location {
+        context.insert("file".to_string(), location.file().into());
+        context.insert("line".to_string(), location.line().into());
+        context.insert("column".to_string(), location.column().into());
+    }
+    let current = thread::current();
+    context.insert(
+        "thread".to_string(),
+        Value::from(current.name().unwrap_or("<unnamed>")),
+    );
+
+    let mut event = Event {
+        exception: vec![Exception {
+            ty: "panic".to_string(),
+            value: Some(message.to_string()),
+            stacktrace: current_stacktrace(),
+            mechanism: Some(Mechanism {
+                ty: "panic".to_string(),
+                handled: Some(false),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]
+        .into(),
+        level: Level::Fatal,
+        ..Default::default()
+    };
+    event
+        .contexts
+        .insert("panic".to_string(), Context::Other(context));
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_events() {
+        setup_panic_hook();
+
+        let events = sentry::test::with_captured_events(|| {
+            let _ = panic::catch_unwind(|| panic!("literal panic"));
+            let _ = panic::catch_unwind(|| panic!("formatted {}", "panic"));
+        });
+
+        assert_eq!(events.len(), 2);
+        let exception = &events[0].exception[0];
+        assert_eq!(exception.ty, "panic");
+        assert_eq!(exception.value.as_deref(), Some("literal panic"));
+        assert_eq!(events[0].level, Level::Fatal);
+        assert_eq!(
+            events[1].exception[0].value.as_deref(),
+            Some("formatted panic")
+        );
+
+        let context = match events[0].contexts.get("panic") {
+            Some(Context::Other(context)) => context,
+            other => panic!("unexpected panic context: {:?}", other),
+        };
+        assert_eq!(context["file"], Value::from(file!()));
+        assert_eq!(
+            context["thread"],
+            Value::from(thread::current().name().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_hook_installed_once() {
+        let _first = PanicHook::new().install();
+        let _second = PanicHook::new().call_previous(false).install();
+        setup_panic_hook();
+
+        let events = sentry::test::with_captured_events(|| {
+            let _ = panic::catch_unwind(|| panic!("reported once"));
+        });
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_resumed_panic_reported_once() {
+        let events = sentry::test::with_captured_events(|| {
+            let inner = panic::catch_unwind(|| panic!("resumed panic")).unwrap_err();
+            let location = report_caught_panic(inner.as_ref());
+            let outer = panic::catch_unwind(AssertUnwindSafe(|| resume_reported(inner, location)))
+                .unwrap_err();
+            report_caught_panic(outer.as_ref());
+            assert_eq!(message_from_payload(outer.as_ref()), "resumed panic");
+        });
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_resumed_panic_caught_elsewhere() {
+        let events = sentry::test::with_captured_events(|| {
+            let inner = catch_unwind(|| panic!("first panic")).unwrap_err();
+            let location = report_caught_panic(inner.as_ref());
+            // The resumed panic is caught by code that does not report it.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| resume_reported(inner, location)));
+
+            for i in 0..20 {
+                let result = crate::run_sync_with_error_reporting(|| -> std::io::Result<()> {
+                    panic!("second panic number {}", i)
+                });
+                assert!(result.is_err());
+            }
+        });
+        assert_eq!(events.len(), 21);
+        assert_eq!(
+            events[1].exception[0].value.as_deref(),
+            Some("second panic number 0")
+        );
+    }
+
+    #[test]
+    fn test_guard_dropped_while_panicking() {
+        let result = panic::catch_unwind(|| {
+            let _guard = PanicHook::new().install();
+            panic!("dropped while unwinding");
+        });
+        assert!(result.is_err());
+        assert!(is_installed());
+    }
+}
@@ -0,0 +1,453 @@
+use sentry::protocol::Value;
+use sentry::{TransactionContext, TransactionOrSpan};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+pub use sentry::protocol::SpanStatus;
+
+/// Start a transaction, the root of a tree of timed spans such as a request
+/// handler or a batch job.
+///
+/// The transaction is finished and sent when the returned guard is dropped.
+/// Whether it is sent at all is decided when it is started, according to
+/// `Config::traces_sample_rate`.
+///
+/// While the guard is alive, the transaction is the current span of the
+/// scope, so errors reported in the meantime are linked to it.
+///
+/// Do not hold the guard across an `.await`: the transaction would stay
+/// current while other tasks run on the same thread, and their spans would
+/// be recorded as its children. Pass the work to [`Transaction::bind`]
+/// instead, unless the task runs with its own hub.
+///
+/// ```no_run
+/// use drcode_rust::{start_transaction, SpanStatus};
+///
+/// let transaction = start_transaction("POST /orders", "http.server");
+/// {
+///     let span = transaction.start_child("db.query", "INSERT INTO orders");
+///     span.set_data("rows", 1);
+/// }
+/// transaction.set_status(SpanStatus::Ok);
+/// ```
+pub fn start_transaction(name: &str, op: &str) -> Transaction {
+    let transaction = sentry::start_transaction(TransactionContext::new(name, op));
+    Transaction {
+        active: Active::start(transaction.into()),
+    }
+}
+
+/// Start a span as a child of the current span or transaction.
+///
+/// When no span is current, a new transaction named `description` is
+/// started instead, so the span is never lost. It is finished when dropped.
+pub fn start_span(op: &str, description: &str) -> Span {
+    Span {
+        active: Active::start(new_span(op, description)),
+    }
+}
+
+/// Start a span like [`start_span`] without making it the current span.
+///
+/// Use [`Instrumented`] to make it current while the work it times runs.
+#[cfg(feature = "macros")]
+pub(crate) fn start_detached_span(op: &str, description: &str) -> Span {
+    Span {
+        active: Active::new(new_span(op, description), None),
+    }
+}
+
+fn new_span(op: &str, description: &str) -> TransactionOrSpan {
+    match sentry::configure_scope(|scope| scope.get_span()) {
+        Some(parent) => parent.start_child(op, description).into(),
+        None => sentry::start_transaction(TransactionContext::new(description, op)).into(),
+    }
+}
+
+/// A running transaction, finished when dropped.
+#[must_use = "the transaction is finished when the guard is dropped"]
+pub struct Transaction {
+    active: Active,
+}
+
+impl Transaction {
+    /// Start a span timing one step of this transaction.
+    pub fn start_child(&self, op: &str, description: &str) -> Span {
+        self.active.start_child(op, description)
+    }
+
+    /// Set the status the transaction finishes with.
+    pub fn set_status(&self, status: SpanStatus) {
+        self.active.span.set_status(status);
+    }
+
+    /// Attach a piece of data to the transaction.
+    pub fn set_data(&self, key: &str, value: impl Into<Value>) {
+        self.active.span.set_data(key, value.into());
+    }
+
+    /// Finish the transaction now rather than when it goes out of scope.
+    pub fn finish(self) {}
+
+    /// Run `future` in this transaction, which is only current while the
+    /// future is polled and is finished when it completes.
+    ///
+    /// ```no_run
+    /// use drcode_rust::{start_span, start_transaction};
+    ///
+    /// async fn load_orders() {
+    ///     let _span = start_span("db.query", "SELECT * FROM orders");
+    /// }
+    ///
+    /// # async fn run() {
+    /// start_transaction("GET /orders", "http.server")
+    ///     .bind(load_orders())
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn bind<F: Future>(self, future: F) -> Instrumented<Transaction, F> {
+        self.active.exit();
+        Instrumented::new(self.active.span.clone(), self, future)
+    }
+
+    /// Attach the data of the HTTP request the transaction is handling.
+    #[cfg(any(feature = "actix", feature = "tower"))]
+    pub(crate) fn set_request(&self, request: sentry::protocol::Request) {
+        self.active.span.set_request(request);
+    }
+}
+
+/// A running span, finished when dropped.
+#[must_use = "the span is finished when the guard is dropped"]
+pub struct Span {
+    active: Active,
+}
+
+impl Span {
+    /// Start a span timing one step of this span.
+    pub fn start_child(&self, op: &str, description: &str) -> Span {
+        self.active.start_child(op, description)
+    }
+
+    /// Set the status the span finishes with.
+    pub fn set_status(&self, status: SpanStatus) {
+        self.active.span.set_status(status);
+    }
+
+    /// Attach a piece of data to the span.
+    pub fn set_data(&self, key: &str, value: impl Into<Value>) {
+        self.active.span.set_data(key, value.into());
+    }
+
+    /// Finish the span now rather than when it goes out of scope.
+    pub fn finish(self) {}
+
+    /// Run `future` in this span, which is only current while the future is
+    /// polled and is finished when it completes.
+    pub fn bind<F: Future>(self, future: F) -> Instrumented<Span, F> {
+        self.active.exit();
+        Instrumented::new(self.active.span.clone(), self, future)
+    }
+
+    #[cfg(feature = "macros")]
+    pub(crate) fn span(&self) -> TransactionOrSpan {
+        self.active.span.clone()
+    }
+}
+
+/// Future returned by [`Transaction::bind`] and [`Span::bind`].
+///
+/// It makes its span current each time the future is polled, and restores
+/// the previous one after each poll. Making the span current once for the
+/// whole future would leave it current across `.await` points, where other
+/// futures polled on the same thread would record their spans as its
+/// children.
+#[must_use = "futures do nothing unless polled"]
+pub struct Instrumented<G, F> {
+    future: Pin<Box<F>>,
+    span: TransactionOrSpan,
+    guard: Option<G>,
+}
+
+impl<G, F: Future> Instrumented<G, F> {
+    pub(crate) fn new(span: TransactionOrSpan, guard: G, future: F) -> Self {
+        Instrumented {
+            future: Box::pin(future),
+            span,
+            guard: Some(guard),
+        }
+    }
+}
+
+impl<G: Unpin, F: Future> Future for Instrumented<G, F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let poll = {
+            let _entered = Entered {
+                parent: make_current(this.span.clone()),
+            };
+            this.future.as_mut().poll(cx)
+        };
+        if poll.is_ready() {
+            this.guard = None;
+        }
+        poll
+    }
+}
+
+/// Restores the span that was current before its span was made current.
+struct Entered {
+    parent: Option<TransactionOrSpan>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let parent = self.parent.take();
+        sentry::configure_scope(|scope| scope.set_span(parent));
+    }
+}
+
+/// Make `span` the current span of the scope, returning the previous one.
+fn make_current(span: TransactionOrSpan) -> Option<TransactionOrSpan> {
+    sentry::configure_scope(|scope| {
+        let parent = scope.get_span();
+        scope.set_span(Some(span));
+        parent
+    })
+}
+
+/// Identify a span by its `sentry-trace` header, the only place the span
+/// itself rather than its transaction exposes its id.
+fn span_id(span: &TransactionOrSpan) -> Option<String> {
+    span.iter_headers().next().map(|(_, value)| value)
+}
+
+/// The state of an [`Active`] span, shared with the spans started while it
+/// was current.
+struct State {
+    /// The span that was current when this one started.
+    parent: Option<TransactionOrSpan>,
+    /// The state of `parent`, when it is an `Active` span.
+    parent_state: Option<Arc<State>>,
+    finished: AtomicBool,
+}
+
+impl State {
+    /// The span to make current again when this span finishes: its parent,
+    /// or the nearest ancestor still running if the parent finished first.
+    fn restore(&self) -> Option<TransactionOrSpan> {
+        let (mut parent, mut state) = (&self.parent, &self.parent_state);
+        while let Some(next) = state {
+            if !next.finished.load(Ordering::Acquire) {
+                break;
+            }
+            (parent, state) = (&next.parent, &next.parent_state);
+        }
+        parent.clone()
+    }
+}
+
+/// The states of the running `Active` spans, by span id.
+static STATES: Mutex<Vec<(String, Arc<State>)>> = Mutex::new(Vec::new());
+
+fn states() -> MutexGuard<'static, Vec<(String, Arc<State>)>> {
+    STATES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A transaction or span that is the current span of the scope until dropped.
+struct Active {
+    span: TransactionOrSpan,
+    state: Arc<State>,
+}
+
+impl Active {
+    fn start(span: TransactionOrSpan) -> Active {
+        let parent = make_current(span.clone());
+        Active::new(span, parent)
+    }
+
+    fn new(span: TransactionOrSpan, parent: Option<TransactionOrSpan>) -> Active {
+        let parent_state = parent.as_ref().and_then(|parent| {
+            let id = span_id(parent)?;
+            let states = states();
+            let (_, state) = states.iter().find(|(other, _)| *other == id)?;
+            Some(state.clone())
+        });
+        let state = Arc::new(State {
+            parent,
+            parent_state,
+            finished: AtomicBool::new(false),
+        });
+        if let Some(id) = span_id(&span) {
+            states().push((id, state.clone()));
+        }
+        Active { span, state }
+    }
+
+    fn start_child(&self, op: &str, description: &str) -> Span {
+        Span {
+            active: Active::start(self.span.start_child(op, description).into()),
+        }
+    }
+
+    /// Restore the span that was current before this one, unless another
+    /// span has been made current since.
+    fn exit(&self) {
+        let id = span_id(&self.span);
+        sentry::configure_scope(|scope| {
+            if scope.get_span().map(|span| span_id(&span)) == Some(id) {
+                scope.set_span(self.state.restore());
+            }
+        });
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        if self.span.get_status().is_none() {
+            self.span.set_status(if std::thread::panicking() {
+                SpanStatus::InternalError
+            } else {
+                SpanStatus::Ok
+            });
+        }
+
+        self.state.finished.store(true, Ordering::Release);
+        if let Some(id) = span_id(&self.span) {
+            states().retain(|(other, _)| *other != id);
+        }
+        self.exit();
+
+        self.span.clone().finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{capture_items, events, transactions};
+    use sentry::protocol::{Context, Map};
+
+    #[test]
+    fn test_transaction() {
+        let items = capture_items(1.0, || {
+            let transaction = start_transaction("POST /orders", "http.server");
+            transaction.set_data("tenant", "acme");
+            {
+                let span = transaction.start_child("db.query", "INSERT INTO orders");
+                span.set_data("rows", 1);
+                span.start_child("db.commit", "COMMIT")
+                    .set_status(SpanStatus::Aborted);
+            }
+            crate::report_error(&std::io::Error::other("failed"));
+        });
+
+        assert_eq!(items.len(), 2);
+        let (event, transaction) = (events(&items)[0], transactions(&items)[0]);
+        let trace_id = |contexts: &Map<String, Context>| {
+            match contexts.get("trace") {
+                Some(Context::Trace(trace)) => Some(trace.trace_id),
+                _ => None,
+            }
+        };
+        assert!(trace_id(&event.contexts).is_some());
+        assert_eq!(trace_id(&event.contexts), trace_id(&transaction.contexts));
+        assert_eq!(transaction.name.as_deref(), Some("POST /orders"));
+        assert_eq!(transaction.extra["tenant"], Value::from("acme"));
+        assert_eq!(transaction.spans.len(), 2);
+
+        let commit = &transaction.spans[0];
+        let query = &transaction.spans[1];
+        assert_eq!(commit.op.as_deref(), Some("db.commit"));
+        assert_eq!(commit.status, Some(SpanStatus::Aborted));
+        assert_eq!(commit.parent_span_id, Some(query.span_id));
+        assert_eq!(query.description.as_deref(), Some("INSERT INTO orders"));
+        assert_eq!(query.data["rows"], Value::from(1));
+        assert_eq!(query.status, Some(SpanStatus::Ok));
+        assert!(query.timestamp.unwrap() >= query.start_timestamp);
+    }
+
+    #[test]
+    fn test_start_span() {
+        let items = capture_items(1.0, || {
+            start_span("task", "nightly-import")
+                .start_child("db.query", "SELECT 1")
+                .finish();
+            let transaction = start_transaction("POST /orders", "http.server");
+            start_span("db.query", "INSERT INTO orders").set_data("rows", 1);
+            transaction.finish();
+        });
+
+        let transactions = transactions(&items);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].name.as_deref(), Some("nightly-import"));
+        assert_eq!(transactions[0].spans.len(), 1);
+        assert_eq!(transactions[1].spans[0].op.as_deref(), Some("db.query"));
+    }
+
+    #[test]
+    fn test_drop_out_of_order() {
+        let current = || sentry::configure_scope(|scope| scope.get_span().map(|s| span_id(&s)));
+        capture_items(1.0, || {
+            let transaction = start_transaction("import", "task");
+            let span = start_span("db.query", "SELECT 1");
+            drop(transaction);
+            drop(span);
+            assert_eq!(current(), None);
+
+            let transaction = start_transaction("import", "task");
+            let outer = transaction.start_child("file.read", "orders.csv");
+            let inner = start_span("db.query", "INSERT INTO orders");
+            drop(outer);
+            drop(inner);
+            assert_eq!(current(), Some(span_id(&transaction.active.span)));
+        });
+    }
+
+    #[test]
+    fn test_bind() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let items = capture_items(1.0, || {
+            runtime.block_on(async {
+                let a = start_transaction("handler A", "http.server").bind(async {
+                    tokio::task::yield_now().await;
+                    start_span("db.query", "from handler A").finish();
+                });
+                let b = async {
+                    tokio::task::yield_now().await;
+                    start_span("db.query", "from handler B").finish();
+                };
+                tokio::join!(a, b);
+            });
+            assert!(sentry::configure_scope(|scope| scope.get_span()).is_none());
+        });
+
+        let transactions = transactions(&items);
+        assert_eq!(transactions.len(), 2);
+        let a = transactions
+            .iter()
+            .find(|t| t.name.as_deref() == Some("handler A"))
+            .unwrap();
+        assert_eq!(a.spans.len(), 1);
+        assert_eq!(a.spans[0].description.as_deref(), Some("from handler A"));
+        assert!(transactions
+            .iter()
+            .any(|t| t.name.as_deref() == Some("from handler B") && t.spans.is_empty()));
+    }
+
+    #[test]
+    fn test_transaction_sampling() {
+        let items = capture_items(0.0, || {
+            let transaction = start_transaction("POST /orders", "http.server");
+            transaction.start_child("db.query", "INSERT INTO orders").finish();
+        });
+        assert!(items.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+pub use sentry::protocol::{Context, IpAddress, User};
+pub use sentry::Scope;
+
+/// Configure the current scope, affecting every event sent from it afterwards.
+///
+/// Use it to set tags, extras, the user, contexts, a fingerprint or a level
+/// for the rest of a request or job. Called on the main thread before other
+/// threads are started, it configures the scope those threads start with.
+/// Without an initialized client `f` is not called and `R::default()` is returned.
+///
+/// ```no_run
+/// use drcode_rust::{configure_scope, User};
+///
+/// configure_scope(|scope| {
+///     scope.set_tag("tenant", "acme");
+///     scope.set_user(Some(User {
+///         id: Some("42".to_string()),
+///         email: Some("jane@example.com".to_string()),
+///         ..Default::default()
+///     }));
+/// });
+/// ```
+pub fn configure_scope<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Scope) -> R,
+    R: Default,
+{
+    sentry::configure_scope(f)
+}
+
+/// Run `callback` in a temporary scope configured by `scope_config`.
+///
+/// The temporary scope starts as a copy of the current scope and is
+/// discarded when `callback` returns, so the changes only apply to events
+/// sent from within `callback`.
+///
+/// ```no_run
+/// use drcode_rust::{report_error, with_scope, Level};
+///
+/// # let error = std::io::Error::other("timeout");
+/// with_scope(
+///     |scope| {
+///         scope.set_tag("job", "nightly-import");
+///         scope.set_fingerprint(Some(&["nightly-import", "timeout"]));
+///         scope.set_level(Some(Level::Warning));
+///     },
+///     || report_error(&error),
+/// );
+/// ```
+pub fn with_scope<C, F, R>(scope_config: C, callback: F) -> R
+where
+    C: FnOnce(&mut Scope),
+    F: FnOnce() -> R,
+{
+    sentry::with_scope(scope_config, callback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{report_error, Level};
+    use sentry::protocol::Value;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_scope_enrichment() {
+        let error = std::io::Error::other("payment declined");
+        let events = sentry::test::with_captured_events(|| {
+            configure_scope(|scope| scope.set_tag("tenant", "acme"));
+            with_scope(
+                |scope| {
+                    scope.set_extra("order_id", Value::from(1234));
+                    scope.set_user(Some(User {
+                        id: Some("42".to_string()),
+                        email: Some("jane@example.com".to_string()),
+                        ip_address: Some(IpAddress::Exact(IpAddr::V4(Ipv4Addr::LOCALHOST))),
+                        username: Some("jane".to_string()),
+                        ..Default::default()
+                    }));
+                    scope.set_fingerprint(Some(&["payments", "declined"]));
+                    scope.set_level(Some(Level::Warning));
+                },
+                || report_error(&error),
+            );
+            report_error(&error);
+        });
+
+        assert_eq!(events.len(), 2);
+        let scoped = &events[0];
+        assert_eq!(scoped.tags["tenant"], "acme");
+        assert_eq!(scoped.extra["order_id"], Value::from(1234));
+        assert_eq!(scoped.user.as_ref().unwrap().username.as_deref(), Some("jane"));
+        assert_eq!(scoped.fingerprint.as_ref(), ["payments", "declined"]);
+        assert_eq!(scoped.level, Level::Warning);
+
+        let unscoped = &events[1];
+        assert_eq!(unscoped.tags["tenant"], "acme");
+        assert!(unscoped.extra.is_empty());
+        assert!(unscoped.user.is_none());
+        assert_eq!(unscoped.level, Level::Error);
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers shared by the unit tests.
+
+use sentry::protocol::{EnvelopeItem, Event, Transaction};
+
+/// Run `f` with a test client and return the items of the envelopes it sent.
+pub(crate) fn capture_items(traces_sample_rate: f32, f: impl FnOnce()) -> Vec<EnvelopeItem> {
+    let options = sentry::ClientOptions {
+        traces_sample_rate,
+        ..Default::default()
+    };
+    sentry::test::with_captured_envelopes_options(f, options)
+        .iter()
+        .flat_map(|envelope| envelope.items().cloned())
+        .collect()
+}
+
+/// The events among captured envelope items.
+pub(crate) fn events(items: &[EnvelopeItem]) -> Vec<&Event<'static>> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            EnvelopeItem::Event(event) => Some(event),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The transactions among captured envelope items.
+pub(crate) fn transactions(items: &[EnvelopeItem]) -> Vec<&Transaction<'static>> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            EnvelopeItem::Transaction(transaction) => Some(transaction),
+            _ => None,
+        })
+        .collect()
+}